@@ -0,0 +1,166 @@
+use crate::core::*;
+
+use std::collections::BTreeMap;
+
+use zen_colour::*;
+
+/// Spent or received amount of one transaction: transaction, account, amount, received?
+type Flow = (usize, usize, f32, bool);
+
+/// What a monthly series adds up
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Category{
+    Total,
+    Account(usize),
+    Tag(usize),
+    Untagged,
+}
+
+/// The spread of a baseline is at least this part of its mean,
+/// so a month is not flagged for differing a little from months that were all the same
+const MIN_SPREAD: f32 = 0.1;
+
+/// Flag months whose spending or receiving, in total, per account or per tag,
+/// is further from the months before it than `--threshold` standard deviations.
+/// With real values the transactions are expressed like the frames, with the factor of their month.
+pub fn anomalies(
+    args: &lapp::Args, nb: &NameBank, ts: &[Trans], hist: &[Vec<f32>], start: MonthDate,
+    real_factors: Option<&[f32]>)
+{
+    let (textc, infoc, namec, posc, negc, fracc, b, r) =
+        (DEFAULT, MAGENTA, BLUE, GREEN, RED, YELLOW, BOLD, RESET);
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+    let baseline = args.get_integer("baseline").max(1) as usize;
+    let threshold = args.get_float("threshold");
+    let top = args.get_integer("top").max(1) as usize;
+    let redact = args.get_bool("redact");
+    if hist.len() <= baseline{
+        println!("There are not more months than the baseline of {} months to compare.", baseline);
+        return;
+    }
+
+    // the frame of the month of a date, undated directives belong to the first month
+    let frame_index = |(_, month, year): Date| if year == 0{
+        0
+    } else {
+        ((year as i64 - start.1 as i64) * 12 + month as i64 - start.0 as i64)
+            .clamp(0, hist.len() as i64 - 1) as usize
+    };
+
+    // the same rules as the spending and receiving statistics, logged per transaction
+    let mut state = State::new(nb);
+    state.flow_log = Some(Vec::new());
+    let mut months: Vec<Vec<Flow>> = vec![Vec::new(); hist.len()];
+    for (i, trans) in ts.iter().enumerate(){
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        let log = state.flow_log.replace(Vec::new()).unwrap_or_default();
+        let index = frame_index(trans.date());
+        let factor = real_factors.and_then(|factors| factors.get(index)).copied().unwrap_or(1.0);
+        months[index].extend(log.into_iter()
+            .map(|(account, amount, received)| (i, account, amount * factor, received)));
+    }
+    let mut series: BTreeMap<(Category, bool), Vec<f32>> = BTreeMap::new();
+    series.insert((Category::Total, false), hist.iter().map(|frame| frame[SPENDING_MONTH]).collect());
+    series.insert((Category::Total, true), hist.iter().map(|frame| frame[RECEIVING_MONTH]).collect());
+    for (month, flows) in months.iter().enumerate(){
+        for (trans, account, amount, received) in flows{
+            let mut categories = vec![Category::Account(*account)];
+            let tags = ts[*trans].tags();
+            if tags.is_empty(){
+                categories.push(Category::Untagged);
+            }
+            categories.extend(tags.iter().map(|tag| Category::Tag(*tag)));
+            for category in categories{
+                let amounts = series.entry((category, *received)).or_insert_with(|| vec![0.0; hist.len()]);
+                amounts[month] += amount;
+            }
+        }
+    }
+
+    // month, category, received?, amount, mean and spread of the baseline
+    let mut found = Vec::new();
+    for ((category, received), amounts) in &series{
+        // a closed account spends and receives nothing from the month it is closed in
+        let end = match category{
+            Category::Account(account) => nb.account_closed(*account).map(frame_index),
+            _ => None,
+        };
+        for month in baseline..end.unwrap_or(amounts.len()){
+            let window = &amounts[month - baseline..month];
+            let mean = window.iter().sum::<f32>() / baseline as f32;
+            let sd = (window.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / baseline as f32).sqrt();
+            let spread = sd.max(mean.abs() * MIN_SPREAD);
+            let deviation = amounts[month] - mean;
+            if deviation.abs() >= 0.005 && deviation.abs() > threshold * spread{
+                found.push((month, *category, *received, amounts[month], mean, spread));
+            }
+        }
+    }
+    found.sort_by_key(|(month, category, received, ..)| (*month, !*received, *category));
+
+    println!("{}{b}Anomalies{r} (baseline of {} months, {} standard deviations):",
+        infoc, baseline, threshold
+    );
+    if found.is_empty(){
+        println!("  {}Nothing stands out.", textc);
+        return;
+    }
+    let label = |month: usize|{
+        let mut date = start;
+        for _ in 0..month{
+            date = next_month(date);
+        }
+        format!("{:02}/{}", date.0, date.1)
+    };
+    let mut last_month = None;
+    for (month, category, received, amount, mean, spread) in found{
+        if last_month != Some(month){
+            println!("  {}{}{}:", namec, label(month), textc);
+            last_month = Some(month);
+        }
+        let name = match category{
+            Category::Total => String::from("total"),
+            Category::Account(account) => format!("account {}", nb.account_name(account)),
+            Category::Tag(tag) => format!("tag {}", nb.tag_name(tag)),
+            Category::Untagged => String::from("untagged"),
+        };
+        let (kind, amountc) = if received { ("received", posc) } else { ("spent", negc) };
+        let change = if mean.abs() >= 0.005{
+            format!("{}{:+}%{}", fracc, val((amount / mean - 1.0) * 100.0), textc)
+        } else {
+            format!("{}new{}", fracc, textc)
+        };
+        if redact{
+            println!("    {}{} {}: {}", textc, name, kind, change);
+        } else {
+            println!("    {}{} {}: {}{}{} against {}{}{} ± {}, {}",
+                textc, name, kind, amountc, val(amount), textc, amountc, val(mean), textc,
+                val(spread), change
+            );
+        }
+
+        // the biggest transactions of the month counted in the series
+        let counted = |(trans, account, _, rec): &&Flow| *rec == received && match category{
+            Category::Total => true,
+            Category::Account(id) => *account == id,
+            Category::Tag(tag) => ts[*trans].tags().contains(&tag),
+            Category::Untagged => ts[*trans].tags().is_empty(),
+        };
+        let mut behind = months[month].iter().filter(counted).collect::<Vec<_>>();
+        behind.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        for (trans, account, amount, _) in behind.into_iter().take(top){
+            let trans = &ts[*trans];
+            let tags = trans.tags().iter().map(|tag| nb.tag_name(*tag)).collect::<Vec<_>>().join(",");
+            let amount = if redact{
+                String::new()
+            } else {
+                format!(" {}{}{}", amountc, val(*amount), textc)
+            };
+            let id = trans.id().map(|id| format!(" @{}", id)).unwrap_or_default();
+            println!("      {}{} {}{} {}{}{}{} {}",
+                textc, format_date(trans.date()), trans.command(), id, namec, nb.account_name(*account),
+                textc, amount, tags
+            );
+        }
+    }
+}
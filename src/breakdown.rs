@@ -0,0 +1,97 @@
+use crate::core::*;
+
+use std::collections::HashMap;
+
+use zen_colour::*;
+
+/// Spent or received amount of one transaction: account, tags, amount, received?
+type Flow = (usize, Vec<usize>, f32, bool);
+
+/// Break spending and receiving down per account and per tag, per month or year and in total
+pub fn breakdown(nb: &NameBank, ts: &[Trans], by_year: bool, top: usize, redact: bool){
+    let (textc, infoc, b, r) = (DEFAULT, MAGENTA, BOLD, RESET);
+
+    // the same rules as the spending and receiving statistics, logged per transaction
+    let mut state = State::new(nb);
+    state.flow_log = Some(Vec::new());
+    let mut periods: Vec<(String, Vec<Flow>)> = Vec::new();
+    for trans in ts{
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        let log = state.flow_log.replace(Vec::new()).unwrap_or_default();
+        if log.is_empty() { continue; }
+        let (_, month, year) = trans.date();
+        let label = if by_year { year.to_string() } else { format!("{:02}/{}", month, year) };
+        if periods.last().map(|(last, _)| *last != label).unwrap_or(true){
+            periods.push((label, Vec::new()));
+        }
+        if let Some((_, flows)) = periods.last_mut(){
+            flows.extend(log.into_iter()
+                .map(|(account, amount, received)| (account, trans.tags().to_vec(), amount, received)));
+        }
+    }
+    let all = periods.iter().flat_map(|(_, flows)| flows.iter().cloned()).collect::<Vec<_>>();
+    periods.push((String::from("Total"), all));
+
+    for (label, flows) in periods{
+        println!("{}{b}{}{r}:", infoc, label);
+        print_flows(nb, &flows, false, top, redact);
+        print_flows(nb, &flows, true, top, redact);
+    }
+    println!("{}Transactions with more than one tag count for each of them.", textc);
+}
+
+fn print_flows(nb: &NameBank, flows: &[Flow], received: bool, top: usize, redact: bool){
+    let (textc, namec, posc, negc, fracc) = (DEFAULT, BLUE, GREEN, RED, YELLOW);
+    let flows = flows.iter().filter(|(_, _, _, rec)| *rec == received).collect::<Vec<_>>();
+    let total: f32 = flows.iter().map(|(_, _, amount, _)| amount).sum();
+    if flows.is_empty() || total.abs() < 0.005 { return; }
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+    let amountc = if received { posc } else { negc };
+
+    let mut accounts = HashMap::new();
+    let mut tags = HashMap::new();
+    for (account, trans_tags, amount, _) in &flows{
+        *accounts.entry(nb.account_name(*account)).or_insert(0.0) += amount;
+        if trans_tags.is_empty(){
+            *tags.entry(String::from("untagged")).or_insert(0.0) += amount;
+        }
+        for tag in trans_tags{
+            *tags.entry(nb.tag_name(*tag)).or_insert(0.0) += amount;
+        }
+    }
+    // the biggest categories first, the rest together
+    let categories = |map: HashMap<String, f32>|{
+        let mut sorted = map.into_iter().collect::<Vec<_>>();
+        sorted.sort_by(|(name_a, a), (name_b, b)|
+            b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal).then(name_a.cmp(name_b))
+        );
+        let other: f32 = sorted.iter().skip(top).map(|(_, amount)| amount).sum();
+        let mut parts = sorted.into_iter().take(top).map(|(name, amount)|{
+            let share = amount / total * 100.0;
+            if redact{
+                format!("{}{}{} {}{}%{}", namec, name, textc, fracc, val(share), textc)
+            } else {
+                format!("{}{}{} {}{}{} ({}{}%{})",
+                    namec, name, textc, amountc, val(amount), textc, fracc, val(share), textc)
+            }
+        }).collect::<Vec<_>>();
+        if other.abs() >= 0.005{
+            let share = other / total * 100.0;
+            parts.push(if redact{
+                format!("other {}{}%{}", fracc, val(share), textc)
+            } else {
+                format!("other {}{}{} ({}{}%{})", amountc, val(other), textc, fracc, val(share), textc)
+            });
+        }
+        parts.join(", ")
+    };
+
+    let kind = if received { "Received" } else { "Spent" };
+    if redact{
+        println!("  {}{}:", textc, kind);
+    } else {
+        println!("  {}{}: {}{}{}", textc, kind, amountc, val(total), textc);
+    }
+    println!("    {}accounts: {}", textc, categories(accounts));
+    println!("    {}tags: {}", textc, categories(tags));
+}
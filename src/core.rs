@@ -1,6 +1,6 @@
 use term_basics_linux as tbl;
 
-use std::collections::{ HashMap };
+use std::collections::{ HashMap, BTreeMap };
 
 pub const REAL_FIAT: usize = 0;
 pub const FIAT: usize = 1;
@@ -17,8 +17,13 @@ pub const SPENDING_MONTH: usize = 8;
 pub const SPENDING_CUMULATIVE: usize = 9;
 pub const RECEIVING_MONTH: usize = 10;
 pub const RECEIVING_CUMULATIVE: usize = 11;
+pub const SPENDING_12M: usize = 12;
+pub const RECEIVING_12M: usize = 13;
+pub const SAVING_RATE_12M: usize = 14;
+pub const NET_MONTHS: usize = 15;
+pub const MWR: usize = 16;
 
-pub const NR_BUILDIN_ACCOUNTS: usize = 12;
+pub const NR_BUILDIN_ACCOUNTS: usize = 17;
 
 pub type NamedBalance = (String, f32); // Name, value
 pub type NamedBalanceStat = (String, f32, bool); // Name, value, statistic?
@@ -36,39 +41,275 @@ pub fn into_named_assets(bs: &[f32], state: &NameBank) -> Vec<NamedBalance>{
 
 pub type MonthDate = (u8, u16);
 
+pub fn next_month(date: MonthDate) -> MonthDate{
+    if date.0 == 12{
+        (1, date.1 + 1)
+    } else {
+        (date.0 + 1, date.1)
+    }
+}
+
+/// Price index values from the cpi lines
+pub fn cpi_series(ts: &[Trans]) -> Vec<(Date, f32)>{
+    ts.iter().filter_map(|t| match t.ext{
+        TransExt::Cpi { index } => Some((t.date, index)),
+        _ => None,
+    }).collect()
+}
+
+/// The yearly rate at which the cash flows are worth nothing together
+pub fn xirr(cash_flows: &[(i64, f64)]) -> Option<f64>{
+    let first = cash_flows.first()?.0;
+    let npv = |rate: f64| cash_flows.iter()
+        .map(|(day, cf)| cf / (1.0 + rate).powf((day - first) as f64 / 365.0))
+        .sum::<f64>();
+    let (mut low, mut high) = (-0.9999, 100.0);
+    if npv(low).signum() == npv(high).signum(){
+        return None;
+    }
+    // the value goes down as the rate goes up, so halve the interval until it is small enough
+    for _ in 0..100{
+        let mid = (low + high) / 2.0;
+        if npv(mid).signum() == npv(low).signum(){
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+/// The last day of a month
+pub fn month_end(date: MonthDate) -> Date{
+    let next = next_month(date);
+    let days = date_days((1, next.0, next.1)) - date_days((1, date.0, date.1));
+    (days as u8, date.0, date.1)
+}
+
+/// Tag of the transactions that post the interest of the int lines
+pub const INTEREST_TAG: &str = "interest";
+
+/// Where a transaction comes from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Origin{
+    Line(usize),
+    Interest(usize), // posted for the int directive at this line
+}
+
+impl std::fmt::Display for Origin{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+        match self{
+            Origin::Line(line) => write!(f, "{}", line),
+            Origin::Interest(line) => write!(f, "interest of {}", line),
+        }
+    }
+}
+
+/// Insert a transaction at the end of every interest period of the int lines that posts the interest
+/// accrued over it, every day accrues on the balance at the end of that day.
+/// Interest accrues up to the end of the month of the last dated transaction, the last month of the history.
+/// Gives the transactions with their origin and the interest accrued after the last posting.
+pub fn accrue_interest(ts: Vec<Trans>, lines: Vec<usize>, nb: &mut NameBank)
+    -> (Vec<Trans>, Vec<Origin>, Vec<f32>)
+{
+    let tag = nb.tag_id(INTEREST_TAG.to_string());
+    let mut state = State::new(nb);
+    let mut terms = BTreeMap::new(); // account, (yearly interest in %, months in a period, line)
+    let mut accrued = BTreeMap::new();
+    let mut out = Vec::with_capacity(ts.len());
+    let mut origins = Vec::with_capacity(lines.len());
+    let mut last: Option<(i64, MonthDate)> = None; // first day not accrued yet and its month
+    for (trans, line) in ts.into_iter().zip(lines){
+        if trans.date.2 != 0{
+            let today = date_days(trans.date);
+            let (mut day, mut month) = last.unwrap_or((today, (trans.date.1, trans.date.2)));
+            while date_days(month_end(month)) < today{
+                let end_day = date_days(month_end(month));
+                accrue(&state, &terms, &mut accrued, end_day + 1 - day);
+                day = end_day + 1;
+                let postings = post_interest(&mut state, &terms, &mut accrued, month, tag);
+                origins.extend(postings.iter().map(|(_, line)| Origin::Interest(*line)));
+                out.extend(postings.into_iter().map(|(posting, _)| posting));
+                month = next_month(month);
+            }
+            accrue(&state, &terms, &mut accrued, today - day);
+            last = Some((today, month));
+        }
+        update(std::slice::from_ref(&trans), &mut state, None, None);
+        match trans.ext{
+            TransExt::Int { account, rate, months } => {
+                terms.insert(account, (rate, months, line));
+            },
+            // a closed account earns nothing anymore, what it accrued since the last posting is lost
+            TransExt::Clo { account, .. } => {
+                terms.remove(&account);
+                accrued.remove(&account);
+            },
+            _ => { },
+        }
+        out.push(trans);
+        origins.push(Origin::Line(line));
+    }
+    // the last month accrues up to its end like the months before it
+    if let Some((day, month)) = last{
+        accrue(&state, &terms, &mut accrued, date_days(month_end(month)) + 1 - day);
+        let postings = post_interest(&mut state, &terms, &mut accrued, month, tag);
+        origins.extend(postings.iter().map(|(_, line)| Origin::Interest(*line)));
+        out.extend(postings.into_iter().map(|(posting, _)| posting));
+    }
+    let mut unposted = vec![0.0; nb.next_account_id()];
+    for (account, amount) in accrued{
+        unposted[account] = amount;
+    }
+    (out, origins, unposted)
+}
+
+/// Post what the accounts accrued at the end of the month if their period ends in it,
+/// gives the postings with the line of their int directive
+fn post_interest(
+    state: &mut State, terms: &BTreeMap<usize, (f32, u8, usize)>, accrued: &mut BTreeMap<usize, f32>,
+    month: MonthDate, tag: usize) -> Vec<(Trans, usize)>
+{
+    let mut postings = Vec::new();
+    for (account, (_, months, line)) in terms{
+        if !month.0.is_multiple_of(*months) { continue; }
+        let amount = (accrued.remove(account).unwrap_or(0.0) * 100.0).round() / 100.0;
+        if amount == 0.0 { continue; }
+        let ext = if amount > 0.0{
+            TransExt::Mov{ src: NULL, dst: *account, amount }
+        } else {
+            TransExt::Mov{ src: *account, dst: NULL, amount: -amount }
+        };
+        let posting = Trans{
+            date: month_end(month), tags: vec![tag], ext, deferred: Vec::new(), id: None,
+            status: Status::Unmarked,
+        };
+        update(std::slice::from_ref(&posting), state, None, None);
+        postings.push((posting, *line));
+    }
+    postings
+}
+
+/// Interest over a number of days on the balances of the accounts with interest terms
+fn accrue(
+    state: &State, terms: &BTreeMap<usize, (f32, u8, usize)>, accrued: &mut BTreeMap<usize, f32>, days: i64)
+{
+    for (account, (rate, _, _)) in terms{
+        *accrued.entry(*account).or_insert(0.0) +=
+            state.accounts[*account] * rate / 100.0 * days as f32 / 365.0;
+    }
+}
+
+/// The price index at a day, interpolated between the known values and constant outside them
+fn cpi_at(cpi: &[(i64, f32)], day: i64) -> f32{
+    match cpi.iter().position(|(d, _)| *d >= day){
+        Some(0) => cpi[0].1,
+        Some(i) => {
+            let ((d0, v0), (d1, v1)) = (cpi[i - 1], cpi[i]);
+            v0 + (v1 - v0) * (day - d0) as f32 / (d1 - d0) as f32
+        },
+        None => cpi[cpi.len() - 1].1,
+    }
+}
+
+/// Express every frame in the money of the base date, frames hold the balances at the end of
+/// their month. Returns series stay as they are. Gives the factor every frame is multiplied with.
+pub fn deflate(
+    hist: &mut [Vec<f32>], start: MonthDate, cpi: &[(Date, f32)], base: Date, nb: &NameBank) -> Vec<f32>
+{
+    let mut cpi = cpi.iter().map(|(date, index)| (date_days(*date), *index)).collect::<Vec<_>>();
+    cpi.sort_by_key(|(day, _)| *day);
+    if cpi.is_empty() { return vec![1.0; hist.len()]; }
+    let base = cpi_at(&cpi, date_days(base));
+    let mut date = start;
+    let mut factors = Vec::with_capacity(hist.len());
+    let mut spending = 0.0;
+    let mut receiving = 0.0;
+    for frame in hist.iter_mut(){
+        date = next_month(date);
+        let factor = base / cpi_at(&cpi, date_days((1, date.0, date.1)) - 1);
+        factors.push(factor);
+        scale_values(frame, factor, nb);
+        // sums over time add up the months in the money of each month
+        spending += frame[SPENDING_MONTH];
+        receiving += frame[RECEIVING_MONTH];
+        frame[SPENDING_CUMULATIVE] = spending;
+        frame[RECEIVING_CUMULATIVE] = receiving;
+    }
+    factors
+}
+
+/// The returns series and other series that are not an amount of money
+pub fn is_ratio(id: usize, nb: &NameBank) -> bool{
+    id == ROI || id == MWR || id == SAVING_RATE_12M || id == NET_MONTHS
+        || nb.roi_accounts().iter().any(|(_, roi, mwr)| *roi == id || *mwr == id)
+}
+
+/// Multiply all balances except the ratios
+pub fn scale_values(bs: &mut [f32], factor: f32, nb: &NameBank){
+    for (id, value) in bs.iter_mut().enumerate(){
+        if !is_ratio(id, nb){
+            *value *= factor;
+        }
+    }
+}
+
 pub fn hist(state: &mut State, ts: &[Trans]) -> (Vec<Vec<f32>>, MonthDate){
     let mut hist = Vec::new();
     if ts.is_empty() { return (hist, (0, 0)); }
     let mut from = 0;
-    let mut date = (ts[0].date.1, ts[0].date.2);
+    // directives before the first date have year 0, they belong to the first month
+    let mut date = ts.iter().map(|t| (t.date.1, t.date.2)).find(|(_, y)| *y != 0)
+        .unwrap_or((0, 0));
     let start_date = date;
-    let mut prev_frame = Vec::new();
-    state.accounts[ROI] = 1.0;
     loop{
         let (new_from, new_date) = update(ts, state, Some(from), Some(date));
-        // we have a frame for every month, fill in months the data skips
-        while (new_date.0 < date.0 + 1 && new_date.1 <= date.1)
-                && !(new_date.0 == 1 && new_date.1 == date.1 + 1)
-                && (new_date != date){
-            hist.push(prev_frame.clone());
-            date = if date.0 == 12{
-                (1, date.1 + 1)
-            } else {
-                (date.0 + 1, date.1)
-            };
+        if date.1 != 0{
+            state.money_weighted(date_days(month_end(date)));
         }
         let frame = state.accounts.clone();
         hist.push(frame.clone());
         if new_from >= ts.len(){
             break;
         }
+        // we have a frame for every month, months without transactions keep the balances
+        let mut gap = next_month(date);
+        while (gap.1, gap.0) < (new_date.1, new_date.0){
+            let mut frame = frame.clone();
+            frame[SPENDING_MONTH] = 0.0;
+            frame[RECEIVING_MONTH] = 0.0;
+            hist.push(frame);
+            gap = next_month(gap);
+        }
         from = new_from;
         date = new_date;
-        prev_frame = frame;
     }
+    rolling_series(&mut hist, state);
     (hist, start_date)
 }
 
+/// Fill in the sums over the past 12 months for every frame and what follows from them,
+/// the state gets the ones of the last frame
+pub fn rolling_series(hist: &mut [Vec<f32>], state: &mut State){
+    for i in 0..hist.len(){
+        let window = &hist[i.saturating_sub(11)..=i];
+        let spending: f32 = window.iter().map(|frame| frame[SPENDING_MONTH]).sum();
+        let receiving: f32 = window.iter().map(|frame| frame[RECEIVING_MONTH]).sum();
+        // the first year has fewer months to average the spending over
+        let months = window.len() as f32;
+        let frame = &mut hist[i];
+        frame[SPENDING_12M] = spending;
+        frame[RECEIVING_12M] = receiving;
+        frame[SAVING_RATE_12M] = if receiving != 0.0 { (receiving - spending) / receiving * 100.0 } else { 0.0 };
+        frame[NET_MONTHS] = if spending != 0.0 { frame[NET] / spending * months } else { 0.0 };
+    }
+    if let Some(last) = hist.last(){
+        for id in [SPENDING_12M, RECEIVING_12M, SAVING_RATE_12M, NET_MONTHS]{
+            state.accounts[id] = last[id];
+        }
+    }
+}
+
 pub fn update(ts: &[Trans], state: &mut State, from: Option<usize>, from_date: Option<MonthDate>)
     -> (usize, MonthDate)
 {
@@ -78,151 +319,320 @@ pub fn update(ts: &[Trans], state: &mut State, from: Option<usize>, from_date: O
     let mut spending_acc = 0.0;
     let mut receiving_acc = 0.0;
     for (i, trans) in ts.iter().skip(skip).enumerate(){
-        if !all && (trans.date.1 != date.0 || trans.date.2 != date.1){
+        let undated = trans.date.2 == 0;
+        if !all && !undated && (trans.date.1 != date.0 || trans.date.2 != date.1){
             let next = skip + i;
             let date = (trans.date.1, trans.date.2);
             state.accounts[SPENDING_MONTH] = spending_acc;
             state.accounts[SPENDING_CUMULATIVE] += spending_acc;
             state.accounts[RECEIVING_MONTH] = receiving_acc;
             state.accounts[RECEIVING_CUMULATIVE] += receiving_acc;
+            state.eval_formulas();
             return (next, date);
-        } else {
+        } else if !undated {
             date = (trans.date.1, trans.date.2);
         }
+        if state.skip_pending && trans.status == Status::Pending{
+            continue;
+        }
 
-        match trans.ext{
-            TransExt::Set { amount, dst } => {
-                let diff = amount - state.accounts[dst];
-                if dst != NULL{
-                    state.accounts[NET] += diff;
-                    if state.account_labels[dst] == AccountLabel::Assets{
-                        state.accounts[YIELD] += diff;
-                        state.accounts[ASSETS] += diff;
-                    }
+        let resolved;
+        let ext = if trans.deferred.is_empty(){
+            &trans.ext
+        } else {
+            let mut ext = trans.ext.clone();
+            for (field, expr) in &trans.deferred{
+                match state.eval(expr){
+                    Ok(value) => ext.set_field(field, value),
+                    Err(err) => state.errors.push((skip + i, TransErr::FloatError(
+                        field.to_string(), expr.clone(), format!("{}", err)
+                    ))),
                 }
-                if state.account_labels[dst] == AccountLabel::Fiat{
-                    state.asset_amounts[REAL_FIAT] += diff;
+            }
+            resolved = ext;
+            &resolved
+        };
+        if let Some(id) = &trans.id{
+            let inverse = inverse(ext, state);
+            state.inverses.insert(id.clone(), inverse);
+        }
+        let before = state.investments();
+        apply(ext, state, skip + i, &mut spending_acc, &mut receiving_acc);
+        state.track_returns(&before, if undated { None } else { Some(date_days(trans.date)) });
+    }
+    if !all{
+        state.accounts[SPENDING_MONTH] = spending_acc;
+        state.accounts[SPENDING_CUMULATIVE] += spending_acc;
+        state.accounts[RECEIVING_MONTH] = receiving_acc;
+        state.accounts[RECEIVING_CUMULATIVE] += receiving_acc;
+    }
+    state.eval_formulas();
+    (usize::MAX, date)
+}
+
+/// Apply the effects of a single transaction
+fn apply(
+    ext: &TransExt, state: &mut State, index: usize,
+    spending_acc: &mut f32, receiving_acc: &mut f32)
+{
+    match *ext{
+        TransExt::Set { amount, dst } => {
+            let diff = amount - state.accounts[dst];
+            if dst != NULL{
+                state.accounts[NET] += diff;
+                if state.account_labels[dst] == AccountLabel::Assets{
+                    state.accounts[YIELD] += diff;
+                    state.accounts[ASSETS] += diff;
                 }
-                state.accounts[dst] = amount;
-            },
-            TransExt::Mov { src, dst, amount } => {
-                state.accounts[src] -= amount;
-                state.accounts[dst] += amount;
-                state.accounts[FLOW] += amount;
-                let srcl = state.account_labels[src];
-                let dstl = state.account_labels[dst];
-                if srcl == AccountLabel::Assets{
-                    state.accounts[ASSETS] -= amount;
+            }
+            if state.account_labels[dst] == AccountLabel::Fiat{
+                state.asset_amounts[REAL_FIAT] += diff;
+            }
+            state.accounts[dst] = amount;
+        },
+        TransExt::Mov { src, dst, amount } => {
+            mov(state, src, dst, amount, spending_acc, receiving_acc);
+        },
+        TransExt::Inc { asset, account, amount } => {
+            // income is always received, also as the first money into an account
+            state.account_initialised[account] = true;
+            mov(state, NULL, account, amount, spending_acc, receiving_acc);
+            // what an investment pays out is a return of it, not money put into it
+            state.flows[account] -= amount;
+            if let Some(log) = &mut state.income_log{
+                log.push((asset, account, amount));
+            }
+        },
+        TransExt::Tra { src, dst, sub, add } => {
+            state.accounts[src] -= sub;
+            state.accounts[dst] += add;
+            state.flows[src] -= sub;
+            state.flows[dst] += add;
+            state.log_transfer(src, dst, sub, add);
+            state.accounts[FLOW] += sub.max(add);
+            let srcl = state.account_labels[src];
+            let dstl = state.account_labels[dst];
+            if srcl == AccountLabel::Assets{
+                state.accounts[ASSETS] -= sub;
+            }
+            if dstl == AccountLabel::Assets{
+                state.accounts[ASSETS] += add;
+            }
+            let diff = add - sub;
+            state.accounts[TRA] += diff;
+            if src != NULL && dst != NULL && dstl != AccountLabel::Stat{
+                state.accounts[INTERNAL_FLOW] += sub.max(add);
+                state.accounts[NET] += diff;
+            } else if src != NULL && dst == NULL{
+                state.accounts[NET] -= sub;
+                if srcl != AccountLabel::Debt{
+                    *spending_acc += sub;
+                    state.log_flow(src, sub, false);
                 }
+            } else if src == NULL && dst != NULL && dstl != AccountLabel::Stat{
+                state.accounts[NET] += add;
+                if dstl != AccountLabel::Debt && state.account_initialised[dst]{
+                    *receiving_acc += sub;
+                    state.log_flow(dst, sub, true);
+                }
+                state.account_initialised[dst] = true;
+            }
+            if srcl == AccountLabel::Fiat &&
+                dstl != AccountLabel::Fiat &&
+                dstl != AccountLabel::Stat
+            {
+                state.asset_amounts[REAL_FIAT] -= sub;
+                // When used correctly, this FIAT is converted away to assets
                 if dstl == AccountLabel::Assets{
-                    state.accounts[ASSETS] += amount;
-                }
-                if src != NULL && dst != NULL && dstl != AccountLabel::Stat{
-                    state.accounts[INTERNAL_FLOW] += amount;
-                } else if src != NULL && dst == NULL{
-                    state.accounts[NET] -= amount;
-                    if srcl != AccountLabel::Debt{
-                        spending_acc += amount;
-                    }
-                } else if src == NULL && dst != NULL && dstl != AccountLabel::Stat{
-                    state.accounts[NET] += amount;
-                    let init = &mut state.account_initialised[dst];
-                    if dstl != AccountLabel::Debt && *init{
-                        receiving_acc += amount;
-                    }
-                    *init = true;
-                }
-                if srcl == AccountLabel::Fiat &&
-                    dstl != AccountLabel::Fiat &&
-                    dstl != AccountLabel::Stat
-                {
-                    state.asset_amounts[REAL_FIAT] -= amount;
-                    // When used correctly, this FIAT is converted away to assets
-                    if dstl == AccountLabel::Assets{
-                        state.asset_amounts[FIAT] += amount;
-                    }
-                } else if dstl == AccountLabel::Fiat && srcl != AccountLabel::Fiat{
-                    state.asset_amounts[REAL_FIAT] += amount;
-                    // When used correctly, this FIAT is converted away to assets
-                    if srcl == AccountLabel::Assets{
-                        state.asset_amounts[FIAT] -= amount;
-                    }
+                    state.asset_amounts[FIAT] += add;
                 }
-            },
-            TransExt::Tra { src, dst, sub, add } => {
-                state.accounts[src] -= sub;
-                state.accounts[dst] += add;
-                state.accounts[FLOW] += sub.max(add);
-                let srcl = state.account_labels[src];
-                let dstl = state.account_labels[dst];
+            } else if dstl == AccountLabel::Fiat && srcl != AccountLabel::Fiat{
+                state.asset_amounts[REAL_FIAT] += add;
+                // When used correctly, this FIAT is converted away to assets
+                // convert X assets to ADD fiat, making FIAT 0 again
                 if srcl == AccountLabel::Assets{
-                    state.accounts[ASSETS] -= sub;
-                }
-                if dstl == AccountLabel::Assets{
-                    state.accounts[ASSETS] += add;
-                }
-                let diff = add - sub;
-                state.accounts[TRA] += diff;
-                if src != NULL && dst != NULL && dstl != AccountLabel::Stat{
-                    state.accounts[INTERNAL_FLOW] += sub.max(add);
-                    state.accounts[NET] += diff;
-                } else if src != NULL && dst == NULL{
-                    state.accounts[NET] -= sub;
-                    if srcl != AccountLabel::Debt{
-                        spending_acc += sub;
-                    }
-                } else if src == NULL && dst != NULL && dstl != AccountLabel::Stat{
-                    state.accounts[NET] += add;
-                    let init = &mut state.account_initialised[dst];
-                    if dstl != AccountLabel::Debt && *init{
-                        receiving_acc += sub;
-                    }
-                    *init = true;
-                }
-                if srcl == AccountLabel::Fiat &&
-                    dstl != AccountLabel::Fiat &&
-                    dstl != AccountLabel::Stat
-                {
-                    state.asset_amounts[REAL_FIAT] -= sub;
-                    // When used correctly, this FIAT is converted away to assets
-                    if dstl == AccountLabel::Assets{
-                        state.asset_amounts[FIAT] += add;
-                    }
-                } else if dstl == AccountLabel::Fiat && srcl != AccountLabel::Fiat{
-                    state.asset_amounts[REAL_FIAT] += add;
-                    // When used correctly, this FIAT is converted away to assets
-                    // convert X assets to ADD fiat, making FIAT 0 again
-                    if srcl == AccountLabel::Assets{
-                        state.asset_amounts[FIAT] -= add;
-                    }
+                    state.asset_amounts[FIAT] -= add;
                 }
-            },
-            TransExt::Dec { asset, amount } => {
-                state.asset_amounts[asset] = amount;
-            },
-            TransExt::Pri { asset, amount, worth } => {
-                state.asset_prices[asset] = worth / amount;
-            },
-            TransExt::Pin { asset, amount, worth } => {
-                state.asset_prices[asset] = worth / amount;
-                state.asset_amounts[asset] = amount;
-            },
-            TransExt::Con { src, src_amount, dst, dst_amount } => {
-                state.asset_amounts[src] -= src_amount;
-                state.asset_amounts[dst] += dst_amount;
-            },
-            TransExt::Ass { account } => {
-                state.account_labels[account] = AccountLabel::Assets;
-            },
-            TransExt::Deb { account } => {
-                state.account_labels[account] = AccountLabel::Debt;
             }
-            TransExt::Stat { account } => {
-                state.account_labels[account] = AccountLabel::Stat;
+        },
+        TransExt::Dec { asset, amount } => {
+            state.asset_amounts[asset] = amount;
+        },
+        TransExt::Pri { asset, amount, worth } => {
+            state.asset_prices[asset] = worth / amount;
+        },
+        TransExt::Pin { asset, amount, worth } => {
+            state.asset_prices[asset] = worth / amount;
+            state.asset_amounts[asset] = amount;
+        },
+        TransExt::Con { src, src_amount, dst, dst_amount } => {
+            state.asset_amounts[src] -= src_amount;
+            state.asset_amounts[dst] += dst_amount;
+        },
+        TransExt::Ass { account } => {
+            state.set_label(account, AccountLabel::Assets);
+        },
+        TransExt::Deb { account } => {
+            state.set_label(account, AccountLabel::Debt);
+        }
+        TransExt::Stat { account } => {
+            state.set_label(account, AccountLabel::Stat);
+        }
+        TransExt::Apr { account, rate, minimum } => {
+            state.debt_terms.insert(account, (rate, minimum));
+        },
+        TransExt::Tgt { ref name, share, tolerance } => {
+            // a new target for the same name replaces the old one
+            match state.targets.iter_mut().find(|(n, _, _)| n == name){
+                Some(target) => *target = (name.clone(), share, tolerance),
+                None => state.targets.push((name.clone(), share, tolerance)),
+            }
+        },
+        TransExt::Cls { asset, ref class } => {
+            state.asset_classes.insert(asset, class.clone());
+        },
+        TransExt::Opn { .. } | TransExt::Cpi { .. } | TransExt::Int { .. } => { },
+        TransExt::Rev { ref id, .. } => {
+            let inverse = state.inverses.get(id).cloned().unwrap_or_default();
+            for undo in inverse{
+                // changes are undone from the balances as they are now, not as they were
+                let ext = match undo{
+                    Undo::Apply(ext) => ext,
+                    Undo::Set { dst, diff } => TransExt::Set { dst, amount: state.accounts[dst] - diff },
+                    Undo::Dec { asset, diff } => TransExt::Dec{
+                        asset, amount: state.asset_amounts[asset] - diff,
+                    },
+                };
+                apply(&ext, state, index, spending_acc, receiving_acc);
+            }
+        },
+        TransExt::Spl { src, ref parts } => {
+            for (dst, amount) in parts{
+                mov(state, src, *dst, *amount, spending_acc, receiving_acc);
             }
+        },
+        TransExt::Fml { account, ref expr } => {
+            match state.eval(expr){
+                Ok(_) => {
+                    state.set_label(account, AccountLabel::Stat);
+                    state.formulas.push((account, expr.clone()));
+                },
+                Err(err) => state.errors.push((index, TransErr::FloatError(
+                    "expression".to_string(), expr.clone(), format!("{}", err)
+                ))),
+            }
+        },
+        TransExt::Clo { account, remainder } => {
+            let balance = state.accounts[account];
+            if let Some(dst) = remainder{
+                mov(state, account, dst, balance, spending_acc, receiving_acc);
+            } else if balance.abs() >= 0.005 { // less than a cent is a rounding error
+                state.errors.push((index, TransErr::CloseBalance(balance)));
+            }
+        },
+    }
+}
+
+/// What undoes part of a transaction
+#[derive(Clone)]
+enum Undo{
+    Apply(TransExt),
+    Set{ dst: usize, diff: f32 }, // the change a set made to the balance
+    Dec{ asset: usize, diff: f32 }, // the change a dec or pin made to the amount
+}
+
+/// What undoes a transaction, given the state right before it is applied.
+/// Flows are undone by applying them with negated amounts so the statistics are undone as well,
+/// set, dec and pin only undo their own change so later changes stay.
+fn inverse(ext: &TransExt, state: &State) -> Vec<Undo>{
+    match *ext{
+        TransExt::Mov { src, dst, amount } => vec![
+            Undo::Apply(TransExt::Mov { src, dst, amount: -amount })
+        ],
+        TransExt::Inc { asset, account, amount } => vec![
+            Undo::Apply(TransExt::Inc { asset, account, amount: -amount })
+        ],
+        TransExt::Tra { src, dst, sub, add } => vec![
+            Undo::Apply(TransExt::Tra { src, dst, sub: -sub, add: -add })
+        ],
+        TransExt::Spl { src, ref parts } => vec![Undo::Apply(TransExt::Spl{
+            src,
+            parts: parts.iter().map(|(dst, amount)| (*dst, -amount)).collect(),
+        })],
+        TransExt::Set { dst, amount } => vec![Undo::Set { dst, diff: amount - state.accounts[dst] }],
+        TransExt::Dec { asset, amount } => vec![
+            Undo::Dec { asset, diff: amount - state.asset_amounts[asset] }
+        ],
+        TransExt::Pri { asset, .. } => vec![
+            Undo::Apply(TransExt::Pri { asset, amount: 1.0, worth: state.asset_prices[asset] }),
+        ],
+        TransExt::Pin { asset, amount, .. } => vec![
+            Undo::Dec { asset, diff: amount - state.asset_amounts[asset] },
+            Undo::Apply(TransExt::Pri { asset, amount: 1.0, worth: state.asset_prices[asset] }),
+        ],
+        TransExt::Con { src, src_amount, dst, dst_amount } => vec![Undo::Apply(TransExt::Con{
+            src, src_amount: -src_amount, dst, dst_amount: -dst_amount,
+        })],
+        // the parser opens the account again, the balance moved to the remainder comes back
+        TransExt::Clo { account, remainder: Some(dst) } => vec![
+            Undo::Apply(TransExt::Mov { src: account, dst, amount: -state.accounts[account] })
+        ],
+        // the parser only allows reversing the above
+        _ => vec![],
+    }
+}
+
+/// Move fiat between accounts, keeping the statistics up to date
+fn mov(
+    state: &mut State, src: usize, dst: usize, amount: f32,
+    spending_acc: &mut f32, receiving_acc: &mut f32)
+{
+    state.accounts[src] -= amount;
+    state.accounts[dst] += amount;
+    state.flows[src] -= amount;
+    state.flows[dst] += amount;
+    state.log_transfer(src, dst, amount, amount);
+    state.accounts[FLOW] += amount;
+    let srcl = state.account_labels[src];
+    let dstl = state.account_labels[dst];
+    if srcl == AccountLabel::Assets{
+        state.accounts[ASSETS] -= amount;
+    }
+    if dstl == AccountLabel::Assets{
+        state.accounts[ASSETS] += amount;
+    }
+    if src != NULL && dst != NULL && dstl != AccountLabel::Stat{
+        state.accounts[INTERNAL_FLOW] += amount;
+    } else if src != NULL && dst == NULL{
+        state.accounts[NET] -= amount;
+        if srcl != AccountLabel::Debt{
+            *spending_acc += amount;
+            state.log_flow(src, amount, false);
+        }
+    } else if src == NULL && dst != NULL && dstl != AccountLabel::Stat{
+        state.accounts[NET] += amount;
+        if dstl != AccountLabel::Debt && state.account_initialised[dst]{
+            *receiving_acc += amount;
+            state.log_flow(dst, amount, true);
+        }
+        state.account_initialised[dst] = true;
+    }
+    if srcl == AccountLabel::Fiat &&
+        dstl != AccountLabel::Fiat &&
+        dstl != AccountLabel::Stat
+    {
+        state.asset_amounts[REAL_FIAT] -= amount;
+        // When used correctly, this FIAT is converted away to assets
+        if dstl == AccountLabel::Assets{
+            state.asset_amounts[FIAT] += amount;
+        }
+    } else if dstl == AccountLabel::Fiat && srcl != AccountLabel::Fiat{
+        state.asset_amounts[REAL_FIAT] += amount;
+        // When used correctly, this FIAT is converted away to assets
+        if srcl == AccountLabel::Assets{
+            state.asset_amounts[FIAT] -= amount;
         }
     }
-    (usize::MAX, date)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -237,9 +647,51 @@ pub enum AccountLabel{
 pub struct State{
     pub accounts: Vec<f32>,
     pub account_labels: Vec<AccountLabel>,
+    pub account_parents: Vec<Option<usize>>,
     pub account_initialised: Vec<bool>,
     pub asset_amounts: Vec<f32>,
     pub asset_prices: Vec<f32>,
+    pub errors: Vec<(usize, TransErr)>, // index of the transaction, error
+    pub formulas: Vec<(usize, String)>, // account, expression
+    pub skip_pending: bool,
+    pub flows: Vec<f32>, // money moved into the account from outside minus what was moved out
+    pub flow_log: Option<Vec<(usize, f32, bool)>>, // account, amount spent or received, received?
+    pub transfer_log: Option<Vec<(usize, usize, f32, f32)>>, // src, dst, subtracted, added
+    pub income_log: Option<Vec<(usize, usize, f32)>>, // asset, account, amount paid out
+    pub debt_terms: HashMap<usize, (f32, f32)>, // account, yearly interest in %, minimum monthly payment
+    pub targets: Vec<(String, f32, f32)>, // asset or asset class, share in %, tolerance in % points
+    pub asset_classes: HashMap<usize, String>,
+    roi_accounts: Vec<(usize, usize, usize)>,
+    cash_flows: Vec<Vec<(i64, f64)>>, // per investment: day, money received (negative when invested)
+    inverses: HashMap<String, Vec<Undo>>, // transaction id, what undoes it
+    names: HashMap<String, Var>,
+}
+
+/// Add the balance of every account to its parent, children have higher ids than their parents
+fn rollup(bs: &[f32], parent: impl Fn(usize) -> Option<usize>) -> Vec<f32>{
+    let mut rolled = bs.to_vec();
+    for id in (0..rolled.len()).rev(){
+        if let Some(parent) = parent(id){
+            rolled[parent] += rolled[id];
+        }
+    }
+    rolled
+}
+
+/// What a name in an expression refers to
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Var{
+    Const(f64),
+    Account(usize),
+    AssetWorth(usize),
+    AssetAmount(usize),
+    AssetPrice(usize),
+}
+
+/// Names in expressions can only contain letters, digits and underscores,
+/// every other character of an account or asset name is written as an underscore
+pub fn expr_name(name: &str) -> String{
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
 }
 
 impl State{
@@ -248,17 +700,157 @@ impl State{
         account_labels[0] = AccountLabel::Null;
         let mut asset_prices = vec![0.0; nb.assets.next_id];
         asset_prices[0] = 1.0;
+        // return series start at 1 and only grow with gains
+        let mut accounts = vec![0.0; nb.accounts.next_id];
+        accounts[ROI] = 1.0;
+        for (_, roi, mwr) in &nb.roi_accounts{
+            accounts[*roi] = 1.0;
+            account_labels[*roi] = AccountLabel::Stat;
+            account_labels[*mwr] = AccountLabel::Stat;
+        }
         Self{
-            accounts: vec![0.0; nb.accounts.next_id],
+            accounts,
             account_labels,
+            account_parents: (0..nb.accounts.next_id).map(|id| nb.account_parent(id)).collect(),
             account_initialised: vec![false; nb.accounts.next_id],
             asset_amounts: vec![0.0; nb.assets.next_id],
             asset_prices,
+            errors: Vec::new(),
+            formulas: Vec::new(),
+            skip_pending: false,
+            flows: vec![0.0; nb.accounts.next_id],
+            flow_log: None,
+            transfer_log: None,
+            income_log: None,
+            debt_terms: HashMap::new(),
+            targets: Vec::new(),
+            asset_classes: HashMap::new(),
+            roi_accounts: nb.roi_accounts.clone(),
+            cash_flows: vec![Vec::new(); nb.roi_accounts.len() + 1],
+            inverses: HashMap::new(),
+            names: nb.expr_names(),
+        }
+    }
+
+    /// Evaluate an expression where names refer to variables, account balances and asset worths,
+    /// amounts and prices (as NAME_amount and NAME_price)
+    pub fn eval(&self, expr: &str) -> Result<f32, fasteval::Error>{
+        let mut ns = |name: &str, _args: Vec<f64>| {
+            self.names.get(name).map(|var| match *var{
+                Var::Const(value) => value as f32,
+                Var::Account(id) => self.accounts[id],
+                Var::AssetWorth(id) => self.asset_amounts[id] * self.asset_prices[id],
+                Var::AssetAmount(id) => self.asset_amounts[id],
+                Var::AssetPrice(id) => self.asset_prices[id],
+            } as f64)
+        };
+        fasteval::ez_eval(expr, &mut ns).map(|ans| ans as f32)
+    }
+
+    /// Recompute the formula accounts, in the order they are defined
+    pub fn eval_formulas(&mut self){
+        for i in 0..self.formulas.len(){
+            let (account, expr) = &self.formulas[i];
+            if let Ok(value) = self.eval(expr){
+                self.accounts[*account] = value;
+            }
+        }
+    }
+
+    /// Remember what is counted as spending or receiving, when the log is kept
+    fn log_flow(&mut self, account: usize, amount: f32, received: bool){
+        if let Some(log) = &mut self.flow_log{
+            log.push((account, amount, received));
+        }
+    }
+
+    /// Remember money moved between accounts, when the log is kept
+    fn log_transfer(&mut self, src: usize, dst: usize, sub: f32, add: f32){
+        if let Some(log) = &mut self.transfer_log{
+            log.push((src, dst, sub, add));
+        }
+    }
+
+    /// Balances where every parent account includes the balances of its children
+    pub fn rollup(&self, bs: &[f32]) -> Vec<f32>{
+        rollup(bs, |id| self.account_parents[id])
+    }
+
+    /// Value and flows of all investment accounts together, then of every account with a return series
+    pub fn investments(&self) -> Vec<(f32, f32)>{
+        let mut total = (0.0, 0.0);
+        for id in 0..self.accounts.len(){
+            if self.account_labels[id] == AccountLabel::Assets{
+                total.0 += self.accounts[id];
+                total.1 += self.flows[id];
+            }
+        }
+        let mut investments = vec![total];
+        if !self.roi_accounts.is_empty(){
+            let values = self.rollup(&self.accounts);
+            let flows = self.rollup(&self.flows);
+            investments.extend(self.roi_accounts.iter().map(|(acc, ..)| (values[*acc], flows[*acc])));
+        }
+        investments
+    }
+
+    /// Grow the return series with what the investments gained since before,
+    /// money moved in or out is no gain but a cash flow at the day of a dated transaction
+    fn track_returns(&mut self, before: &[(f32, f32)], day: Option<i64>){
+        let after = self.investments();
+        let series = std::iter::once(ROI).chain(self.roi_accounts.iter().map(|(_, roi, _)| *roi))
+            .collect::<Vec<_>>();
+        for (i, ((series, (v0, f0)), (v1, f1))) in series.into_iter().zip(before).zip(after).enumerate(){
+            let gain = (v1 - v0) - (f1 - f0);
+            if *v0 > 0.0 && gain != 0.0{
+                self.accounts[series] *= (v0 + gain) / v0;
+            }
+            let mut flow = f1 - f0;
+            // value out of nothing has to be invested by someone
+            if *v0 <= 0.0{
+                flow = v1 - v0;
+            }
+            if let Some(day) = day{
+                if flow.abs() >= 0.005{
+                    self.cash_flows[i].push((day, -flow as f64));
+                }
+            }
+        }
+    }
+
+    /// Cash flows of all investment accounts together, then of every account with a return series
+    pub fn cash_flows(&self) -> &[Vec<(i64, f64)>]{
+        &self.cash_flows
+    }
+
+    /// Set the money-weighted return series to the yearly rate in % of the cash flows so far,
+    /// as if the investments were sold at the day
+    fn money_weighted(&mut self, day: i64){
+        let series = std::iter::once(MWR).chain(self.roi_accounts.iter().map(|(.., mwr)| *mwr))
+            .collect::<Vec<_>>();
+        for (i, (series, (value, _))) in series.into_iter().zip(self.investments()).enumerate(){
+            let mut cash_flows = self.cash_flows[i].clone();
+            cash_flows.push((day, value as f64));
+            self.accounts[series] = xirr(&cash_flows).map(|rate| rate * 100.0).unwrap_or(0.0) as f32;
+        }
+    }
+
+    /// Label an account and all accounts below it in the hierarchy
+    pub fn set_label(&mut self, account: usize, label: AccountLabel){
+        for id in 0..self.account_labels.len(){
+            let mut ancestor = Some(id);
+            while let Some(a) = ancestor{
+                if a == account{
+                    self.account_labels[id] = label;
+                    break;
+                }
+                ancestor = self.account_parents[a];
+            }
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Ider{
     next_id: usize,
     ids: HashMap<String, usize>,
@@ -283,13 +875,21 @@ impl Ider{
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct NameBank{
     accounts: Ider,
     account_names: HashMap<usize, String>,
+    account_parents: HashMap<usize, usize>,
+    account_closed: HashMap<usize, Date>,
     assets: Ider,
     asset_names: HashMap<usize, String>,
     tags: Ider,
+    tag_names: HashMap<usize, String>,
+    account_aliases: HashMap<String, String>,
+    asset_aliases: HashMap<String, String>,
+    vars: HashMap<String, f64>,
+    trans_ids: HashMap<String, (String, Vec<usize>, bool)>, // id, command, accounts, reversed?
+    roi_accounts: Vec<(usize, usize, usize)>, // investment account, its time and money-weighted return series
 }
 
 impl NameBank{
@@ -297,9 +897,17 @@ impl NameBank{
         let temp = Self{
             accounts: Ider::new(),
             account_names: HashMap::new(),
+            account_parents: HashMap::new(),
+            account_closed: HashMap::new(),
             assets: Ider::new(),
             asset_names: HashMap::new(),
             tags: Ider::new(),
+            tag_names: HashMap::new(),
+            account_aliases: HashMap::new(),
+            asset_aliases: HashMap::new(),
+            vars: HashMap::new(),
+            trans_ids: HashMap::new(),
+            roi_accounts: Vec::new(),
         };
         temp.set_defaults()
     }
@@ -317,17 +925,173 @@ impl NameBank{
         self.account_id("_spending_cumulative".to_owned());
         self.account_id("_receiving_month".to_owned());
         self.account_id("_receiving_cumulative".to_owned());
+        self.account_id("_spending_12m".to_owned());
+        self.account_id("_receiving_12m".to_owned());
+        self.account_id("_saving_rate_12m".to_owned());
+        self.account_id("_net_months".to_owned());
+        self.account_id("_mwr".to_owned());
         self.asset_id("REAL_FIAT".to_owned());
         self.asset_id("FIAT".to_owned());
         self
     }
 
+    /// Colon separated names form a hierarchy: Bank:Saving is a child of Bank.
+    /// Parents are registered first so they always have a lower id than their children.
     pub fn account_id(&mut self, string: String) -> usize{
+        let string = self.resolve_account_alias(string);
+        let parent = string.rsplit_once(':').map(|(parent, _)| self.account_id(parent.to_string()));
         let id = self.accounts.get_id(string.clone());
         self.account_names.insert(id, string);
+        if let Some(parent) = parent{
+            self.account_parents.insert(id, parent);
+        }
         id
     }
 
+    /// Everything a name in an expression can refer to
+    fn expr_names(&self) -> HashMap<String, Var>{
+        let mut names = HashMap::new();
+        for id in 0..self.assets.next_id{
+            let name = expr_name(&self.asset_name(id));
+            names.insert(format!("{}_amount", name), Var::AssetAmount(id));
+            names.insert(format!("{}_price", name), Var::AssetPrice(id));
+            names.insert(name, Var::AssetWorth(id));
+        }
+        // accounts win over assets with the same name, variables win over both
+        for id in 0..self.accounts.next_id{
+            names.insert(expr_name(&self.account_name(id)), Var::Account(id));
+        }
+        for (name, value) in &self.vars{
+            names.insert(name.clone(), Var::Const(*value));
+        }
+        names
+    }
+
+    /// What one name in an expression refers to, like expr_names but without building all of them,
+    /// as parsing looks up a name for every amount
+    fn expr_var(&self, name: &str) -> Option<Var>{
+        if let Some(value) = self.vars.get(name){
+            return Some(Var::Const(*value));
+        }
+        let account = (0..self.accounts.next_id).rev()
+            .find(|id| expr_name(&self.account_name(*id)) == name);
+        if let Some(id) = account{
+            return Some(Var::Account(id));
+        }
+        (0..self.assets.next_id).rev().find_map(|id|{
+            let asset = expr_name(&self.asset_name(id));
+            if asset == name{
+                Some(Var::AssetWorth(id))
+            } else if name.strip_suffix("_amount") == Some(&asset){
+                Some(Var::AssetAmount(id))
+            } else if name.strip_suffix("_price") == Some(&asset){
+                Some(Var::AssetPrice(id))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Ids of transactions that can still be reversed
+    pub fn reversible_ids(&self) -> Vec<String>{
+        self.trans_ids.iter()
+            .filter(|(_, (cmd, _, reversed))| !reversed && REVERSIBLE_COMMANDS.contains(&cmd.as_str()))
+            .map(|(id, _)| id.clone()).collect()
+    }
+
+    /// Forget variables, transaction ids and closed accounts so they can be defined again
+    pub fn clear_definitions(&mut self){
+        self.vars.clear();
+        self.trans_ids.clear();
+        self.account_closed.clear();
+    }
+
+    pub fn var(&self, name: &str) -> Option<f64>{
+        self.vars.get(name).copied()
+    }
+
+    /// Accounts and assets have their own aliases, so an account and an asset can share a name
+    pub fn add_alias(&mut self, old: String, new: String, asset: bool){
+        let aliases = if asset { &mut self.asset_aliases } else { &mut self.account_aliases };
+        if old != new{
+            aliases.insert(old, new);
+        }
+    }
+
+    /// Follow aliases to the canonical name, also for the parents of an account
+    fn resolve_account_alias(&self, string: String) -> String{
+        let string = resolve_alias(&self.account_aliases, string);
+        if let Some((parent, leaf)) = string.rsplit_once(':'){
+            let resolved = self.resolve_account_alias(parent.to_string());
+            if resolved != parent{
+                return format!("{}:{}", resolved, leaf);
+            }
+        }
+        string
+    }
+
+    pub fn open_account(&mut self, id: usize){
+        self.account_closed.remove(&id);
+    }
+
+    pub fn close_account(&mut self, id: usize, date: Date){
+        self.account_closed.insert(id, date);
+    }
+
+    /// The date the account got closed, if it is closed
+    pub fn account_closed(&self, id: usize) -> Option<Date>{
+        self.account_closed.get(&id).copied()
+    }
+
+    pub fn account_parent(&self, id: usize) -> Option<usize>{
+        self.account_parents.get(&id).copied()
+    }
+
+    /// Balances where every parent account includes the balances of its children
+    pub fn rollup(&self, bs: &[f32]) -> Vec<f32>{
+        rollup(bs, |id| self.account_parent(id))
+    }
+
+    /// All accounts depth first with their depth in the hierarchy
+    pub fn account_tree(&self) -> Vec<(usize, usize)>{
+        fn visit(nb: &NameBank, id: usize, depth: usize, tree: &mut Vec<(usize, usize)>){
+            tree.push((id, depth));
+            for child in 0..nb.accounts.next_id{
+                if nb.account_parent(child) == Some(id){
+                    visit(nb, child, depth + 1, tree);
+                }
+            }
+        }
+        let mut tree = Vec::new();
+        for id in 0..self.accounts.next_id{
+            if self.account_parent(id).is_none(){
+                visit(self, id, 0, &mut tree);
+            }
+        }
+        tree
+    }
+
+    /// Give an investment account return series: _roi_ and _mwr_ followed by its name
+    pub fn add_roi_account(&mut self, account: usize){
+        if self.roi_accounts.iter().any(|(acc, ..)| *acc == account){
+            return;
+        }
+        let name = expr_name(&self.account_name(account));
+        let roi = self.account_id(format!("_roi_{}", name));
+        let mwr = self.account_id(format!("_mwr_{}", name));
+        self.roi_accounts.push((account, roi, mwr));
+    }
+
+    /// Investment accounts and their time and money-weighted return series
+    pub fn roi_accounts(&self) -> &[(usize, usize, usize)]{
+        &self.roi_accounts
+    }
+
+    /// Id of an existing account, without registering it
+    pub fn find_account(&self, name: &str) -> Option<usize>{
+        self.accounts.ids.get(&self.resolve_account_alias(name.to_string())).copied()
+    }
+
     pub fn account_name(&self, id: usize) -> String{
         if let Some(name) = self.account_names.get(&id){
             name.to_string()
@@ -337,6 +1101,7 @@ impl NameBank{
     }
 
     pub fn asset_id(&mut self, string: String) -> usize{
+        let string = resolve_alias(&self.asset_aliases, string);
         let id = self.assets.get_id(string.clone());
         self.asset_names.insert(id, string);
         id
@@ -351,15 +1116,34 @@ impl NameBank{
     }
 
     pub fn tag_id(&mut self, string: String) -> usize{
-        self.tags.get_id(string)
+        let id = self.tags.get_id(string.clone());
+        self.tag_names.insert(id, string);
+        id
+    }
+
+    pub fn tag_name(&self, id: usize) -> String{
+        if let Some(name) = self.tag_names.get(&id){
+            name.to_string()
+        } else {
+            String::from("unnamed")
+        }
     }
 
     pub fn next_account_id(&self) -> usize{
         self.accounts.next_id
     }
+
+    /// Names of all user accounts, so without the build in ones
+    pub fn user_account_names(&self) -> Vec<String>{
+        (NR_BUILDIN_ACCOUNTS..self.accounts.next_id).map(|id| self.account_name(id)).collect()
+    }
+
+    pub fn asset_names(&self) -> Vec<String>{
+        (0..self.assets.next_id).map(|id| self.asset_name(id)).collect()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TransExt{
     Mov{
         src: usize,
@@ -404,15 +1188,286 @@ pub enum TransExt{
     },
     Stat{
         account: usize,
+    },
+    Opn{
+        account: usize,
+    },
+    Clo{
+        account: usize,
+        remainder: Option<usize>,
+    },
+    Fml{
+        account: usize,
+        expr: String,
+    },
+    Spl{
+        src: usize,
+        parts: Vec<(usize, f32)>, // dst, amount
+    },
+    Rev{
+        id: String,
+        accounts: Vec<usize>, // of the reversed transaction
+    },
+    Cpi{
+        index: f32,
+    },
+    Apr{
+        account: usize,
+        rate: f32,
+        minimum: f32,
+    },
+    Int{
+        account: usize,
+        rate: f32,
+        months: u8, // in a period, after which the interest is posted
+    },
+    Tgt{
+        name: String, // asset or asset class
+        share: f32,
+        tolerance: f32,
+    },
+    Cls{
+        asset: usize,
+        class: String,
+    },
+    Inc{
+        asset: usize,
+        account: usize,
+        amount: f32,
+    },
+}
+
+impl TransExt{
+    /// The command a transaction is written with
+    pub fn command(&self) -> &'static str{
+        match self{
+            TransExt::Mov { .. } => "mov",
+            TransExt::Set { .. } => "set",
+            TransExt::Tra { .. } => "tra",
+            TransExt::Dec { .. } => "dec",
+            TransExt::Pri { .. } => "pri",
+            TransExt::Pin { .. } => "pin",
+            TransExt::Con { .. } => "con",
+            TransExt::Ass { .. } => "ass",
+            TransExt::Deb { .. } => "deb",
+            TransExt::Stat { .. } => "stat",
+            TransExt::Opn { .. } => "opn",
+            TransExt::Clo { .. } => "clo",
+            TransExt::Fml { .. } => "fml",
+            TransExt::Spl { .. } => "spl",
+            TransExt::Rev { .. } => "rev",
+            TransExt::Cpi { .. } => "cpi",
+            TransExt::Apr { .. } => "apr",
+            TransExt::Int { .. } => "int",
+            TransExt::Tgt { .. } => "tgt",
+            TransExt::Cls { .. } => "cls",
+            TransExt::Inc { .. } => "inc",
+        }
+    }
+
+    /// Fill in an amount field by the name it is parsed with
+    fn set_field(&mut self, field: &str, value: f32){
+        match (self, field){
+            (TransExt::Mov { amount, .. }, "amount") => *amount = value,
+            (TransExt::Set { amount, .. }, "amount") => *amount = value,
+            (TransExt::Tra { sub, .. }, "sub") => *sub = value,
+            (TransExt::Tra { add, .. }, "add") => *add = value,
+            (TransExt::Dec { amount, .. }, "amount") => *amount = value,
+            (TransExt::Pri { worth, .. }, "worth") => *worth = value,
+            (TransExt::Pin { worth, .. }, "worth") => *worth = value,
+            (TransExt::Con { src_amount, .. }, "src_amount") => *src_amount = value,
+            (TransExt::Con { dst_amount, .. }, "dst_amount") => *dst_amount = value,
+            (TransExt::Apr { minimum, .. }, "minimum") => *minimum = value,
+            (TransExt::Inc { amount, .. }, "amount") => *amount = value,
+            _ => { },
+        }
+    }
+
+    /// All accounts this transaction touches
+    pub fn accounts(&self) -> Vec<usize>{
+        match self{
+            TransExt::Mov { src, dst, .. } => vec![*src, *dst],
+            TransExt::Set { dst, .. } => vec![*dst],
+            TransExt::Tra { src, dst, .. } => vec![*src, *dst],
+            TransExt::Ass { account } => vec![*account],
+            TransExt::Deb { account } => vec![*account],
+            TransExt::Stat { account } => vec![*account],
+            TransExt::Opn { account } => vec![*account],
+            TransExt::Fml { account, .. } => vec![*account],
+            TransExt::Apr { account, .. } => vec![*account],
+            TransExt::Int { account, .. } => vec![*account],
+            TransExt::Inc { account, .. } => vec![*account],
+            TransExt::Spl { src, parts } => {
+                let mut accounts = vec![*src];
+                accounts.extend(parts.iter().map(|(dst, _)| *dst));
+                accounts
+            },
+            TransExt::Clo { account, remainder } => {
+                let mut accounts = vec![*account];
+                accounts.extend(remainder);
+                accounts
+            },
+            TransExt::Rev { accounts, .. } => accounts.clone(),
+            _ => vec![],
+        }
     }
 }
 
 pub type Date = (u8, u8, u16);
 
+/// Commands whose effects can be undone with rev
+pub const REVERSIBLE_COMMANDS: [&str; 10] =
+    ["mov", "tra", "spl", "set", "dec", "pri", "pin", "con", "inc", "clo"];
+
+/// Commands that have no date field
+pub const DATELESS_COMMANDS: [&str; 3] = ["alias", "fml", "var"];
+
+/// Aliases hold for the whole file, so they are collected before any line is parsed
+pub fn read_aliases(contents: &str, nb: &mut NameBank){
+    for line in contents.split('\n'){
+        let fields = line.split(',').map(|field| field.trim()).collect::<Vec<_>>();
+        if fields[0] == "alias" && fields.len() >= 3{
+            // a wrong kind is reported by the parser
+            let asset = match fields.get(3){
+                None | Some(&"") | Some(&"account") => false,
+                Some(&"asset") => true,
+                Some(_) => continue,
+            };
+            nb.add_alias(fields[1].to_string(), fields[2].to_string(), asset);
+        }
+    }
+}
+
+/// Follow aliases from a name to the canonical one
+fn resolve_alias(aliases: &HashMap<String, String>, string: String) -> String{
+    let mut string = string;
+    // bounded, so a cycle of aliases can't hang us
+    for _ in 0..=aliases.len(){
+        match aliases.get(&string){
+            Some(new) => string = new.clone(),
+            None => break,
+        }
+    }
+    string
+}
+
+/// Sortable number for a date
+pub fn date_sum(date: Date) -> usize{
+    (date.0 as usize) + (date.1 as usize) * 100 + (date.2 as usize) * 10000
+}
+
+/// Number of days since a fixed day long ago, to count the days between dates
+pub fn date_days(date: Date) -> i64{
+    let (d, m, y) = (date.0 as i64, date.1 as i64, date.2 as i64);
+    // years start in march so the leap day is the last day of the year
+    let y = if m <= 2 { y - 1 } else { y };
+    let m = (m + 9) % 12;
+    365 * y + y / 4 - y / 100 + y / 400 + (153 * m + 2) / 5 + d - 1
+}
+
+pub fn parse_date(string: &str) -> Option<Date>{
+    let triple = string.split('/').collect::<Vec<_>>();
+    if triple.len() != 3 { return None; }
+    Some((
+        tbl::string_to_value(triple[0])?,
+        tbl::string_to_value(triple[1])?,
+        tbl::string_to_value(triple[2])?,
+    ))
+}
+
+pub fn format_date(date: Date) -> String{
+    format!("{:02}/{:02}/{}", date.0, date.1, date.2)
+}
+
+/// Whether a transaction showed up on a statement yet, marked with a `!` or `*` tag
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status{
+    Unmarked,
+    Pending,
+    Cleared,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Trans{
     date: Date,
     tags: Vec<usize>,
     ext: TransExt,
+    deferred: Vec<(&'static str, String)>, // field, expression
+    id: Option<String>,
+    status: Status,
+}
+
+impl Trans{
+    pub fn date(&self) -> Date{
+        self.date
+    }
+
+    pub fn tags(&self) -> &[usize]{
+        &self.tags
+    }
+
+    pub fn id(&self) -> Option<&str>{
+        self.id.as_deref()
+    }
+
+    pub fn status(&self) -> Status{
+        self.status
+    }
+
+    pub fn command(&self) -> &'static str{
+        self.ext.command()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldKind{
+    Account,
+    OptionalAccount, // can be left out, written as _ when fields follow it
+    Asset,
+    Amount,
+    Number, // plain number, no expression allowed
+    Parts, // dst=amount;dst=amount;rest-dst
+    Id, // id of an earlier transaction
+    Period, // month, quarter or year
+    Name, // asset or asset class
+    Text, // free text, not used
+}
+
+pub const COMMANDS: [&str; 20] = [
+    "mov", "tra", "spl", "set", "dec", "pri", "pin", "con", "rev", "ass", "deb", "stat", "opn", "clo",
+    "cpi", "apr", "int", "tgt", "cls", "inc"
+];
+
+/// Interest periods of the int command and the months in them
+pub const PERIODS: [(&str, u8); 3] = [("month", 1), ("quarter", 3), ("year", 12)];
+
+/// The fields of a command after the command and date fields, None if the command is unknown
+pub fn command_fields(cmd: &str) -> Option<&'static [(&'static str, FieldKind)]>{
+    use FieldKind::*;
+    Some(match cmd{
+        "dat" => &[],
+        "mov" => &[("src", Account), ("dst", Account), ("amount", Amount), ("description", Text)],
+        "set" => &[("account", Account), ("amount", Amount), ("description", Text)],
+        "tra" => &[
+            ("src", Account), ("dst", Account), ("sub", Amount), ("add", Amount), ("description", Text)
+        ],
+        "dec" => &[("asset", Asset), ("amount", Amount)],
+        "pri" | "pin" => &[("asset", Asset), ("amount", Number), ("worth", Amount)],
+        "con" => &[
+            ("src", Asset), ("src_amount", Amount), ("dst", Asset), ("dst_amount", Amount)
+        ],
+        "ass" | "deb" | "stat" | "opn" => &[("account", Account)],
+        "clo" => &[("account", Account), ("remainder", OptionalAccount)],
+        "spl" => &[("src", Account), ("total", Amount), ("parts", Parts)],
+        "rev" => &[("id", Id)],
+        "cpi" => &[("index", Number)],
+        "apr" => &[("account", Account), ("rate", Number), ("minimum", Amount)],
+        "int" => &[("account", Account), ("rate", Number), ("period", Period)],
+        "tgt" => &[("name", Name), ("share", Number), ("tolerance", Number)],
+        "cls" => &[("asset", Asset), ("class", Name)],
+        "inc" => &[("asset", Asset), ("account", Account), ("amount", Amount)],
+        _ => return None,
+    })
 }
 
 pub type TransRes = Option<Result<Trans, TransErr>>;
@@ -425,6 +1480,14 @@ pub enum TransErr {
     ParseError(String, String),
     FloatError(String, String, String),
     OrderError(Date),
+    ClosedAccount(String, Date),
+    VarRedefined(String),
+    CloseBalance(f32),
+    SplitSum(f32, f32),
+    SplitBalances,
+    DuplicateId(String),
+    UnknownId(String),
+    NotReversible(String),
 }
 
 impl std::fmt::Display for TransErr{
@@ -448,6 +1511,27 @@ impl std::fmt::Display for TransErr{
                 => write!(
                     f, "Illegal order: transaction goes back in time to date: {:?}", date
                 ),
+            TransErr::ClosedAccount(account, date)
+                => write!(
+                    f, "Account '{}' is used after it was closed on {}", account, format_date(*date)
+                ),
+            TransErr::VarRedefined(name)
+                => write!(f, "Variable '{}' is already defined", name),
+            TransErr::SplitSum(total, sum)
+                => write!(f, "The parts of the split add up to {} instead of {}", sum, total),
+            TransErr::SplitBalances
+                => write!(f, "The amounts of a split can not use balances"),
+            TransErr::DuplicateId(id)
+                => write!(f, "Transaction id '{}' is already used", id),
+            TransErr::UnknownId(id)
+                => write!(f, "No earlier transaction has id '{}'", id),
+            TransErr::NotReversible(id)
+                => write!(f, "Transaction '{}' can not be reversed (again)", id),
+            TransErr::CloseBalance(balance)
+                => write!(
+                    f, "Account is closed with a balance of {}, \
+                        give an account to move the remainder to", balance
+                ),
         }
     }
 }
@@ -458,9 +1542,10 @@ pub trait IntoTrans{
 
 impl IntoTrans for String{
     fn into_trans(self, nb: &mut NameBank, date: &mut Date) -> TransRes{
-        if self.is_empty() { return None; }
-        if self.starts_with('#') { return None; }
-        let splitted = self.split(',').collect::<Vec<_>>();
+        let line = self.trim();
+        if line.is_empty() { return None; }
+        if line.starts_with('#') { return None; }
+        let splitted = line.split(',').map(|field| field.trim()).collect::<Vec<_>>();
         if splitted.len() < 2 {
             return Some(Err(TransErr::NotEnoughFields("any command".to_string())));
         }
@@ -477,17 +1562,33 @@ impl IntoTrans for String{
             }
         }
 
+        // expressions using balances are evaluated in update, at the right point in the history
+        let mut deferred = Vec::new();
         macro_rules! parse_float{
-            ($string:expr, $field:expr) => {
-                match fasteval::ez_eval($string, &mut fasteval::EmptyNamespace) {
-                    Ok(ans) => ans as f32,
+            ($string:expr, $field:expr) => {{
+                let mut uses_balances = false;
+                let mut ns = |name: &str, _args: Vec<f64>| match nb.expr_var(name){
+                    Some(Var::Const(value)) => Some(value),
+                    Some(_) => {
+                        uses_balances = true;
+                        Some(0.0)
+                    },
+                    None => None,
+                };
+                match fasteval::ez_eval($string, &mut ns) {
+                    Ok(ans) => {
+                        if uses_balances{
+                            deferred.push(($field, $string.to_string()));
+                        }
+                        ans as f32
+                    },
                     Err(err) => return Some(Err(TransErr::FloatError(
                         $field.to_string(),
                         $string.to_string(),
                         format!("{}", err)
                     ))),
                 }
-            }
+            }}
         }
 
         macro_rules! parse_date{
@@ -510,11 +1611,56 @@ impl IntoTrans for String{
             }
         }
 
-        if splitted[1] != "_"{
+        if splitted[1] != "_" && !DATELESS_COMMANDS.contains(&splitted[0]){
             parse_date!(*date, splitted[1]);
         }
         let tags_ind;
         let ext = match splitted[0]{
+            "alias" => {
+                // registered up front by read_aliases so earlier lines use them as well
+                check_fields!(3, "alias");
+                return match splitted.get(3){
+                    None | Some(&"") | Some(&"account") | Some(&"asset") => None,
+                    Some(kind) => Some(Err(TransErr::ParseError("kind".to_string(), kind.to_string()))),
+                };
+            },
+            "var" => {
+                check_fields!(3, "var");
+                let name = splitted[1];
+                let valid = !name.is_empty() && expr_name(name) == name
+                    && !name.starts_with(|c: char| c.is_ascii_digit());
+                if !valid{
+                    return Some(Err(TransErr::ParseError("var name".to_string(), name.to_string())));
+                }
+                if nb.var(name).is_some(){
+                    return Some(Err(TransErr::VarRedefined(name.to_string())));
+                }
+                let mut ns = |name: &str, _args: Vec<f64>| match nb.expr_var(name){
+                    Some(Var::Const(value)) => Some(value),
+                    _ => None,
+                };
+                match fasteval::ez_eval(splitted[2], &mut ns){
+                    Ok(value) => nb.vars.insert(name.to_string(), value),
+                    Err(err) => return Some(Err(TransErr::FloatError(
+                        name.to_string(), splitted[2].to_string(), format!("{}", err)
+                    ))),
+                };
+                return None;
+            },
+            "fml" => {
+                tags_ind = 3;
+                check_fields!(3, "fml");
+                let mut slab = fasteval::Slab::new();
+                if let Err(err) = fasteval::Parser::new().parse(splitted[2], &mut slab.ps){
+                    return Some(Err(TransErr::FloatError(
+                        "expression".to_string(), splitted[2].to_string(), format!("{}", err)
+                    )));
+                }
+                TransExt::Fml{
+                    account: nb.account_id(splitted[1].to_string()),
+                    expr: splitted[2].to_string(),
+                }
+            },
             "dat" => {
                 check_fields!(2, "date");
                 parse_date!(*date, splitted[1]);
@@ -586,9 +1732,9 @@ impl IntoTrans for String{
             "ass" => {
                 tags_ind = 3;
                 check_fields!(3, "ass");
-                TransExt::Ass{
-                    account: nb.account_id(splitted[2].to_string()),
-                }
+                let account = nb.account_id(splitted[2].to_string());
+                nb.add_roi_account(account);
+                TransExt::Ass{ account }
             },
             "deb" => {
                 tags_ind = 3;
@@ -604,13 +1750,418 @@ impl IntoTrans for String{
                     account: nb.account_id(splitted[2].to_string()),
                 }
             },
+            "opn" => {
+                tags_ind = 3;
+                check_fields!(3, "opn");
+                let account = nb.account_id(splitted[2].to_string());
+                nb.open_account(account);
+                TransExt::Opn{ account }
+            },
+            "clo" => {
+                tags_ind = 4;
+                check_fields!(3, "clo");
+                let remainder = match splitted.get(3){
+                    Some(&"_") | None => None,
+                    Some(remainder) => Some(nb.account_id(remainder.to_string())),
+                };
+                TransExt::Clo{
+                    account: nb.account_id(splitted[2].to_string()),
+                    remainder,
+                }
+            },
+            "cpi" => {
+                tags_ind = 3;
+                check_fields!(3, "cpi");
+                TransExt::Cpi{
+                    index: parse_field!(splitted[2], "index"),
+                }
+            },
+            "apr" => {
+                tags_ind = 5;
+                check_fields!(5, "apr");
+                TransExt::Apr{
+                    account: nb.account_id(splitted[2].to_string()),
+                    rate: parse_field!(splitted[3], "rate"),
+                    minimum: parse_float!(splitted[4], "minimum"),
+                }
+            },
+            "int" => {
+                tags_ind = 5;
+                check_fields!(5, "int");
+                let months = match PERIODS.iter().find(|(name, _)| *name == splitted[4]){
+                    Some((_, months)) => *months,
+                    None => return Some(Err(TransErr::ParseError(
+                        "period".to_string(), splitted[4].to_string()
+                    ))),
+                };
+                TransExt::Int{
+                    account: nb.account_id(splitted[2].to_string()),
+                    rate: parse_field!(splitted[3], "rate"),
+                    months,
+                }
+            },
+            "inc" => {
+                tags_ind = 5;
+                check_fields!(5, "inc");
+                TransExt::Inc{
+                    asset: nb.asset_id(splitted[2].to_string()),
+                    account: nb.account_id(splitted[3].to_string()),
+                    amount: parse_float!(splitted[4], "amount"),
+                }
+            },
+            "tgt" => {
+                tags_ind = 5;
+                check_fields!(5, "tgt");
+                TransExt::Tgt{
+                    name: splitted[2].to_string(),
+                    share: parse_field!(splitted[3], "share"),
+                    tolerance: parse_field!(splitted[4], "tolerance"),
+                }
+            },
+            "cls" => {
+                tags_ind = 4;
+                check_fields!(4, "cls");
+                TransExt::Cls{
+                    asset: nb.asset_id(splitted[2].to_string()),
+                    class: splitted[3].to_string(),
+                }
+            },
+            "rev" => {
+                tags_ind = 3;
+                check_fields!(3, "rev");
+                let id = splitted[2].trim_start_matches('@').to_string();
+                let (cmd, accounts) = match nb.trans_ids.get_mut(&id){
+                    None => return Some(Err(TransErr::UnknownId(id))),
+                    Some((cmd, accounts, reversed)) => {
+                        if *reversed || !REVERSIBLE_COMMANDS.contains(&cmd.as_str()){
+                            return Some(Err(TransErr::NotReversible(id)));
+                        }
+                        *reversed = true;
+                        (cmd.clone(), accounts.clone())
+                    },
+                };
+                // reversing a clo opens the closed account again, its remainder account has to be open
+                if cmd == "clo"{
+                    nb.open_account(accounts[0]);
+                }
+                TransExt::Rev{ id, accounts }
+            },
+            "spl" => {
+                tags_ind = 5;
+                check_fields!(5, "spl");
+                let total = parse_float!(splitted[3], "total");
+                let mut parts = Vec::new();
+                let mut rest = None;
+                for part in splitted[4].split(';').map(|part| part.trim()){
+                    if let Some((dst, amount)) = part.split_once('='){
+                        let dst = nb.account_id(dst.trim().to_string());
+                        parts.push((dst, parse_float!(amount.trim(), "parts")));
+                    } else if rest.is_none(){
+                        rest = Some(nb.account_id(part.to_string()));
+                    } else {
+                        return Some(Err(TransErr::ParseError("parts".to_string(), part.to_string())));
+                    }
+                }
+                // the parts are checked here, so they can't wait for balances
+                if !deferred.is_empty(){
+                    return Some(Err(TransErr::SplitBalances));
+                }
+                let sum: f32 = parts.iter().map(|(_, amount)| amount).sum();
+                if let Some(rest) = rest{
+                    parts.push((rest, total - sum));
+                } else if (total - sum).abs() >= 0.005{
+                    return Some(Err(TransErr::SplitSum(total, sum)));
+                }
+                TransExt::Spl{
+                    src: nb.account_id(splitted[2].to_string()),
+                    parts,
+                }
+            },
             _ => return Some(Err(TransErr::UnknownCommand(splitted[0].to_string()))),
         };
-        let tags = splitted.into_iter().skip(tags_ind).map(|raw_tag| nb.tag_id(raw_tag.to_string()))
-            .collect::<Vec<_>>();
+        for account in ext.accounts(){
+            if let Some(closed) = nb.account_closed(account){
+                return Some(Err(TransErr::ClosedAccount(nb.account_name(account), closed)));
+            }
+        }
+        if let TransExt::Clo { account, .. } = ext{
+            nb.close_account(account, *date);
+        }
+        let mut id = None;
+        let mut status = Status::Unmarked;
+        let mut tags = Vec::new();
+        for raw_tag in splitted.iter().skip(tags_ind){
+            if *raw_tag == "!" || *raw_tag == "*"{
+                if status != Status::Unmarked{
+                    return Some(Err(TransErr::ParseError("status".to_string(), raw_tag.to_string())));
+                }
+                status = if *raw_tag == "!" { Status::Pending } else { Status::Cleared };
+            } else if let Some(raw_id) = raw_tag.strip_prefix('@'){
+                if id.is_some() || raw_id.is_empty(){
+                    return Some(Err(TransErr::ParseError("id".to_string(), raw_tag.to_string())));
+                }
+                if nb.trans_ids.contains_key(raw_id){
+                    return Some(Err(TransErr::DuplicateId(raw_id.to_string())));
+                }
+                id = Some(raw_id.to_string());
+            } else {
+                tags.push(nb.tag_id(raw_tag.to_string()));
+            }
+        }
+        if let Some(id) = &id{
+            nb.trans_ids.insert(id.clone(), (splitted[0].to_string(), ext.accounts(), false));
+        }
 
         Some(Ok(Trans{
-            date: *date, tags, ext
+            date: *date, tags, ext, deferred, id, status
         }))
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn parse(contents: &str) -> (NameBank, Vec<Trans>){
+        let mut nb = NameBank::new();
+        read_aliases(contents, &mut nb);
+        let mut date = Date::default();
+        let ts = contents.lines()
+            .filter_map(|line| line.to_string().into_trans(&mut nb, &mut date))
+            .map(|res| res.unwrap())
+            .collect();
+        (nb, ts)
+    }
+
+    #[test]
+    fn hist_has_a_frame_for_every_month(){
+        let (mut nb, ts) = parse("mov,01/01/2021,null,A,100\nmov,02/01/2021,null,A,100\n\
+            mov,03/01/2021,A,null,30\nmov,01/04/2021,A,null,10");
+        let a = nb.account_id("A".to_string());
+        let mut state = State::new(&nb);
+        let (hist, start) = hist(&mut state, &ts);
+        assert_eq!(start, (1, 2021));
+        // february and march have no transactions, they keep the balances without spending
+        let spending = hist.iter().map(|frame| frame[SPENDING_MONTH]).collect::<Vec<_>>();
+        assert_eq!(spending, vec![30.0, 0.0, 0.0, 10.0]);
+        let balances = hist.iter().map(|frame| frame[a]).collect::<Vec<_>>();
+        assert_eq!(balances, vec![170.0, 170.0, 170.0, 160.0]);
+        // the last month is added up like the others
+        assert_eq!(hist[3][SPENDING_CUMULATIVE], 40.0);
+    }
+
+    #[test]
+    fn xirr_of_known_cash_flows(){
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-6;
+        assert!(close(xirr(&[(0, -1000.0), (365, 1100.0)]), 0.1));
+        assert!(close(xirr(&[(0, -1000.0), (730, 1210.0)]), 0.1));
+        // the example of the spreadsheet XIRR function
+        let flows = [((1, 1, 2008), -10000.0), ((1, 3, 2008), 2750.0), ((30, 10, 2008), 4250.0),
+            ((15, 2, 2009), 3250.0), ((1, 4, 2009), 2750.0)];
+        let flows = flows.iter().map(|(date, cf)| (date_days(*date), *cf)).collect::<Vec<_>>();
+        assert!(close(xirr(&flows), 0.373362535));
+        // without money going both ways no rate makes it worth nothing
+        assert_eq!(xirr(&[(0, -1000.0), (365, -100.0)]), None);
+        assert_eq!(xirr(&[]), None);
+    }
+
+    #[test]
+    fn returns_leave_out_money_moved_in_and_count_income(){
+        let (mut nb, ts) = parse("ass,01/01/2021,broker\nmov,01/01/2021,null,pay,5000\n\
+            mov,01/01/2021,pay,broker,1000\nset,31/12/2021,broker,1100\n\
+            inc,15/06/2022,VWRL,broker,50\nmov,01/07/2022,pay,broker,1000\nset,31/12/2022,broker,2300");
+        let broker = nb.account_id("broker".to_string());
+        let mut state = State::new(&nb);
+        let (hist, _) = hist(&mut state, &ts);
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+        // 10% in 2021, then the dividend and the price both count as gains on top of what was in
+        assert!(close(hist[11][ROI], 1.1));
+        assert!(close(state.accounts[ROI], 1.1 * (1150.0 / 1100.0) * (2300.0 / 2150.0)));
+        let (_, roi, mwr) = nb.roi_accounts()[0];
+        assert_eq!(nb.roi_accounts()[0].0, broker);
+        assert_eq!(state.accounts[roi], state.accounts[ROI]);
+        // the dividend is no cash flow, only the two times money was put in are
+        let flows = vec![(date_days((1, 1, 2021)), -1000.0), (date_days((1, 7, 2022)), -1000.0)];
+        assert_eq!(state.cash_flows()[0], flows);
+        let mut sold = flows.clone();
+        sold.push((date_days((31, 12, 2022)), 2300.0));
+        let rate = xirr(&sold).unwrap() as f32 * 100.0;
+        assert!(close(state.accounts[MWR], rate));
+        assert!(close(state.accounts[mwr], rate));
+    }
+
+    /// Balances of the own accounts right before the transaction with id x and at the end
+    fn reversed(contents: &str) -> (Vec<f32>, Vec<f32>){
+        let (nb, ts) = parse(contents);
+        let mut state = State::new(&nb);
+        let mut before = Vec::new();
+        for trans in &ts{
+            if trans.id() == Some("x"){
+                before = state.accounts[NR_BUILDIN_ACCOUNTS..].to_vec();
+            }
+            update(std::slice::from_ref(trans), &mut state, None, None);
+        }
+        assert!(state.errors.is_empty());
+        (before, state.accounts[NR_BUILDIN_ACCOUNTS..].to_vec())
+    }
+
+    const OPENING: &str = "mov,01/01/2021,null,a,100\nmov,01/01/2021,null,b,50\n";
+
+    #[test]
+    fn rev_restores_balances(){
+        for trans in ["mov,02/01/2021,a,b,30,,@x", "spl,02/01/2021,a,60,b=10;c=20;d,@x",
+                "set,02/01/2021,a,250,,@x", "clo,02/01/2021,a,b,@x"]{
+            let (before, after) = reversed(&format!("{}{}\nrev,03/01/2021,x", OPENING, trans));
+            assert_eq!(before, after, "{}", trans);
+        }
+    }
+
+    #[test]
+    fn rev_of_set_keeps_later_changes(){
+        let (before, after) = reversed(&format!(
+            "{}set,02/01/2021,a,250,,@x\nmov,03/01/2021,null,a,10\nrev,04/01/2021,x", OPENING
+        ));
+        assert_eq!(after[0], before[0] + 10.0);
+    }
+
+    #[test]
+    fn rev_of_clo_opens_the_account(){
+        let (mut nb, _) = parse(&format!("{}clo,02/01/2021,a,b,@x\nrev,03/01/2021,x", OPENING));
+        let a = nb.account_id("a".to_string());
+        assert_eq!(nb.account_closed(a), None);
+        let mut date = Date::default();
+        let used = "mov,04/01/2021,a,b,10".to_string().into_trans(&mut nb, &mut date);
+        assert!(matches!(used, Some(Ok(_))));
+    }
+
+    #[test]
+    fn interest_accrues_to_the_end_of_the_last_month(){
+        let (mut nb, ts) = parse("mov,01/01/2021,null,a,1000\nint,01/01/2021,a,36.5,month\n\
+            mov,10/01/2021,null,b,1");
+        let (ts, origins, accrued) = accrue_interest(ts, vec![1, 2, 3], &mut nb);
+        let lines = vec![Origin::Line(1), Origin::Line(2), Origin::Line(3), Origin::Interest(2)];
+        assert_eq!(origins, lines);
+        let a = nb.account_id("a".to_string());
+        assert_eq!(ts[3].date(), (31, 1, 2021));
+        assert_eq!(ts[3].ext, TransExt::Mov{ src: NULL, dst: a, amount: 31.0 });
+        assert_eq!(accrued[a], 0.0);
+    }
+
+    #[test]
+    fn aliases_per_kind(){
+        let (mut nb, _) = parse("alias,Old,New\nalias,ETF,FUND,asset\nmov,01/01/2021,null,Old:saving,1");
+        assert_eq!(nb.find_account("Old:saving"), nb.find_account("New:saving"));
+        assert_eq!(nb.account_id("Old".to_string()), nb.account_id("New".to_string()));
+        assert_eq!(nb.asset_id("ETF".to_string()), nb.asset_id("FUND".to_string()));
+        // an account alias leaves an asset of the same name alone and the other way around
+        assert_ne!(nb.asset_id("Old".to_string()), nb.asset_id("New".to_string()));
+        assert_ne!(nb.account_id("ETF".to_string()), nb.account_id("FUND".to_string()));
+        let mut date = Date::default();
+        assert!(matches!("alias,a,b,tag".to_string().into_trans(&mut nb, &mut date), Some(Err(_))));
+    }
+
+    #[test]
+    fn rollup_adds_children_once(){
+        let (nb, ts) = parse("mov,01/01/2021,null,Bank:Saving:Extra,5\n\
+            mov,01/01/2021,null,Bank:Payment,10\nmov,01/01/2021,null,Bank:Saving,2");
+        let mut state = State::new(&nb);
+        update(&ts, &mut state, None, None);
+        let rolled = nb.rollup(&state.accounts);
+        assert_eq!(rolled, state.rollup(&state.accounts));
+        let id = |name: &str| nb.find_account(name).unwrap();
+        assert_eq!(rolled[id("Bank:Saving:Extra")], 5.0);
+        assert_eq!(rolled[id("Bank:Saving")], 7.0);
+        assert_eq!(rolled[id("Bank:Payment")], 10.0);
+        // the grandchild is in its parent and through it in the grandparent, not twice
+        assert_eq!(rolled[id("Bank")], 17.0);
+        assert_eq!(nb.account_parent(id("Bank:Saving:Extra")), Some(id("Bank:Saving")));
+    }
+
+    #[test]
+    fn var_and_fml(){
+        let (nb, ts) = parse("var,shares,10\nvar,total,shares*2.5\nmov,01/01/2021,null,a,100\n\
+            mov,02/01/2021,a,b,a/4+shares\nfml,_liquid,a+b*2");
+        assert_eq!(nb.var("total"), Some(25.0));
+        let mut state = State::new(&nb);
+        update(&ts, &mut state, None, None);
+        assert!(state.errors.is_empty());
+        let id = |name: &str| nb.find_account(name).unwrap();
+        // balances in an amount are the ones right before the transaction
+        assert_eq!(state.accounts[id("b")], 35.0);
+        assert_eq!(state.accounts[id("a")], 65.0);
+        assert_eq!(state.accounts[id("_liquid")], 135.0);
+        assert_eq!(state.account_labels[id("_liquid")], AccountLabel::Stat);
+
+        let (mut nb, _) = parse("var,shares,10");
+        let mut date = Date::default();
+        let mut line = |line: &str| line.to_string().into_trans(&mut nb, &mut date);
+        assert!(matches!(line("var,shares,11"), Some(Err(TransErr::VarRedefined(_)))));
+        assert!(matches!(line("var,1st,1"), Some(Err(TransErr::ParseError(..)))));
+        assert!(matches!(line("var,unknown,nothing+1"), Some(Err(TransErr::FloatError(..)))));
+        assert!(matches!(line("fml,_x,a+"), Some(Err(TransErr::FloatError(..)))));
+    }
+
+    #[test]
+    fn spl_parts(){
+        let (nb, ts) = parse("mov,01/01/2021,null,pay,1000\nspl,02/01/2021,pay,600,a=100;b=50*2;c");
+        let id = |name: &str| nb.find_account(name).unwrap();
+        let (a, b, c) = (id("a"), id("b"), id("c"));
+        // the part without an amount gets the rest of the total
+        let parts = vec![(a, 100.0), (b, 100.0), (c, 400.0)];
+        assert_eq!(ts[1].ext, TransExt::Spl{ src: id("pay"), parts });
+        let mut state = State::new(&nb);
+        update(&ts, &mut state, None, None);
+        assert_eq!(state.accounts[id("pay")], 400.0);
+        assert_eq!(state.accounts[c], 400.0);
+
+        let (mut nb, _) = parse("mov,01/01/2021,null,pay,1000");
+        let mut date = Date::default();
+        let mut line = |line: &str| line.to_string().into_trans(&mut nb, &mut date);
+        assert!(matches!(line("spl,01/01/2021,pay,600,a=100;b=100"), Some(Err(TransErr::SplitSum(..)))));
+        assert!(matches!(line("spl,01/01/2021,pay,600,a=100;b;c"), Some(Err(TransErr::ParseError(..)))));
+        // amounts that depend on balances can't be checked against the total
+        assert!(matches!(line("spl,01/01/2021,pay,600,a=pay;b"), Some(Err(TransErr::SplitBalances))));
+        assert!(matches!(line("spl,01/01/2021,pay,600,a=100;b=500"), Some(Ok(_))));
+    }
+
+    #[test]
+    fn cpi_interpolates_and_stays_constant_outside(){
+        let cpi = [(0, 100.0), (10, 110.0), (20, 130.0)];
+        assert_eq!(cpi_at(&cpi, -5), 100.0);
+        assert_eq!(cpi_at(&cpi, 0), 100.0);
+        assert_eq!(cpi_at(&cpi, 5), 105.0);
+        assert_eq!(cpi_at(&cpi, 15), 120.0);
+        assert_eq!(cpi_at(&cpi, 20), 130.0);
+        assert_eq!(cpi_at(&cpi, 50), 130.0);
+    }
+
+    #[test]
+    fn deflate_to_the_money_of_the_base(){
+        let (nb, ts) = parse("mov,01/01/2021,null,a,100\nmov,02/01/2021,a,null,10\n\
+            mov,01/02/2021,a,null,20");
+        let mut state = State::new(&nb);
+        let (mut hist, start) = hist(&mut state, &ts);
+        let a = nb.find_account("a").unwrap();
+        let cpi = [((31, 1, 2021), 100.0), ((28, 2, 2021), 110.0)];
+        let factors = deflate(&mut hist, start, &cpi, (28, 2, 2021), &nb);
+        // frames are the balances at the end of their month
+        assert!((factors[0] - 1.1).abs() < 1e-6 && factors[1] == 1.0);
+        assert!((hist[0][a] - 99.0).abs() < 1e-4 && hist[1][a] == 70.0);
+        assert!((hist[1][SPENDING_CUMULATIVE] - 31.0).abs() < 1e-4);
+        assert_eq!(hist[0][ROI], 1.0);
+        assert_eq!(deflate(&mut hist, start, &[], (28, 2, 2021), &nb), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn date_days_counts_leap_days(){
+        assert_eq!(date_days((1, 3, 2020)) - date_days((28, 2, 2020)), 2);
+        assert_eq!(date_days((1, 3, 2021)) - date_days((28, 2, 2021)), 1);
+        assert_eq!(date_days((1, 1, 2021)) - date_days((1, 1, 2020)), 366);
+        assert_eq!(date_days((1, 1, 2022)) - date_days((1, 1, 2021)), 365);
+        // every 100 years is no leap year, every 400 is
+        assert_eq!(date_days((1, 3, 1900)) - date_days((28, 2, 1900)), 1);
+        assert_eq!(date_days((1, 3, 2000)) - date_days((28, 2, 2000)), 2);
+        assert_eq!(month_end((2, 2024)), (29, 2, 2024));
+        assert_eq!(month_end((12, 2023)), (31, 12, 2023));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::core::*;
+use crate::graph::*;
+
+use zen_colour::*;
+
+/// Stop planning when debts are not paid off within this many months
+const MAX_MONTHS: usize = 1200;
+
+/// A debt as the planner sees it
+struct Debt{
+    name: String,
+    balance: f32,
+    rate: f32, // yearly interest in %
+    minimum: f32, // monthly payment
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Strategy{
+    Avalanche, // highest interest first
+    Snowball, // smallest balance first
+}
+
+/// What paying off the debts with one strategy comes to
+struct Plan{
+    payoff: Vec<Option<usize>>, // months until each debt is paid off
+    interest: Vec<f32>,
+    schedule: Vec<Vec<(f32, f32)>>, // every month: payment and balance after, per debt
+}
+
+/// Pay the interest and minimums every month, the rest of the budget goes to the debt the strategy picks
+fn plan(debts: &[Debt], budget: f32, strategy: Strategy) -> Plan{
+    let mut balances = debts.iter().map(|debt| debt.balance).collect::<Vec<_>>();
+    let mut payoff = vec![None; debts.len()];
+    let mut interest = vec![0.0; debts.len()];
+    let mut schedule = Vec::new();
+    for month in 0..MAX_MONTHS{
+        if balances.iter().all(|balance| *balance < 0.005) { break; }
+        let mut payments = vec![0.0; debts.len()];
+        let mut left = budget;
+        for (i, debt) in debts.iter().enumerate(){
+            if balances[i] < 0.005 { continue; }
+            let added = balances[i] * debt.rate / 1200.0;
+            balances[i] += added;
+            interest[i] += added;
+        }
+        for (i, debt) in debts.iter().enumerate(){
+            let pay = debt.minimum.min(balances[i]).min(left);
+            payments[i] += pay;
+            balances[i] -= pay;
+            left -= pay;
+        }
+        // money freed up by paid off debts rolls over to the next one
+        let mut order = (0..debts.len()).filter(|i| balances[*i] >= 0.005).collect::<Vec<_>>();
+        order.sort_by(|a, b| {
+            let by_rate = debts[*b].rate.partial_cmp(&debts[*a].rate);
+            let by_balance = balances[*a].partial_cmp(&balances[*b]);
+            let ordering = match strategy{
+                Strategy::Avalanche => by_rate.map(|o| o.then(by_balance.unwrap_or(o))),
+                Strategy::Snowball => by_balance.map(|o| o.then(by_rate.unwrap_or(o))),
+            };
+            ordering.unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for i in order{
+            let pay = left.min(balances[i]);
+            payments[i] += pay;
+            balances[i] -= pay;
+            left -= pay;
+        }
+        for i in 0..debts.len(){
+            if balances[i] < 0.005 && payoff[i].is_none(){
+                balances[i] = 0.0;
+                payoff[i] = Some(month + 1);
+            }
+        }
+        schedule.push(payments.into_iter().zip(balances.iter().copied()).collect());
+    }
+    Plan{ payoff, interest, schedule }
+}
+
+/// Plan paying off every debt account with a monthly budget, with the avalanche and snowball strategies
+pub fn debt(args: &lapp::Args, nb: &NameBank, state: &State, date: Date, draw_graph: bool){
+    let (textc, infoc, namec, posc, negc, fracc, b, r) =
+        (DEFAULT, MAGENTA, BLUE, GREEN, RED, YELLOW, BOLD, RESET);
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+    let strategy = match args.get_string("strategy").as_str(){
+        "avalanche" => Strategy::Avalanche,
+        "snowball" => Strategy::Snowball,
+        strategy => {
+            println!("Unknown strategy '{}', use avalanche or snowball.", strategy);
+            return;
+        },
+    };
+
+    // debt accounts go below zero, terms hold for the children of an account as well
+    let mut debts = Vec::new();
+    let mut missing = Vec::new();
+    for id in 0..state.accounts.len(){
+        if state.account_labels[id] != AccountLabel::Debt || state.accounts[id] > -0.005 { continue; }
+        let mut ancestor = Some(id);
+        let mut terms = None;
+        while let Some(a) = ancestor{
+            terms = state.debt_terms.get(&a).copied();
+            if terms.is_some() { break; }
+            ancestor = state.account_parents[a];
+        }
+        if terms.is_none(){
+            missing.push(nb.account_name(id));
+        }
+        let (rate, minimum) = terms.unwrap_or((0.0, 0.0));
+        debts.push(Debt{ name: nb.account_name(id), balance: -state.accounts[id], rate, minimum });
+    }
+    if debts.is_empty(){
+        println!("There are no debts to pay off.");
+        return;
+    }
+    let minimums: f32 = debts.iter().map(|debt| debt.minimum).sum();
+    let budget = args.get_float("budget");
+    let budget = if budget <= 0.0 { minimums } else { budget };
+    if budget <= 0.0{
+        println!("Give a monthly budget with --budget or minimum payments with apr lines.");
+        return;
+    }
+    if budget < minimums{
+        println!("A budget of {} does not cover the minimum payments of {}.", val(budget), val(minimums));
+        return;
+    }
+
+    println!("{}{b}Debts{r}:", infoc);
+    for debt in &debts{
+        println!("  {}{}{}: {}{}{} at {}{}%{} a year, at least {}{}{} a month",
+            namec, debt.name, textc, negc, val(debt.balance), textc, fracc, val(debt.rate), textc,
+            posc, val(debt.minimum), textc
+        );
+    }
+    if !missing.is_empty(){
+        println!("  {}No apr line for {}, planned without interest.", textc, missing.join(", "));
+    }
+
+    // the first planned month is the one after the last transaction
+    let month_after = |months: usize| {
+        let mut month = (date.1, date.2);
+        for _ in 0..months{
+            month = next_month(month);
+        }
+        format!("{:02}/{}", month.0, month.1)
+    };
+    println!("{}{b}Paying {}{}{}{b} a month{r}:", infoc, posc, val(budget), infoc);
+    let plans = [Strategy::Avalanche, Strategy::Snowball]
+        .map(|strategy| (strategy, plan(&debts, budget, strategy)));
+    for (strategy, plan) in &plans{
+        let name = match strategy{
+            Strategy::Avalanche => "Avalanche (highest interest first)",
+            Strategy::Snowball => "Snowball (smallest balance first)",
+        };
+        println!("  {}{}:", textc, name);
+        for (i, debt) in debts.iter().enumerate(){
+            let payoff = match plan.payoff[i]{
+                Some(months) => format!(
+                    "paid off in {}{}{} ({} months)", posc, month_after(months), textc, months
+                ),
+                None => format!("{}not paid off{} in {} years", negc, textc, MAX_MONTHS / 12),
+            };
+            println!("    {}{}{}: {}, interest {}{}{}",
+                namec, debt.name, textc, payoff, negc, val(plan.interest[i]), textc
+            );
+        }
+        let total: f32 = plan.interest.iter().sum();
+        let free = match plan.payoff.iter().copied().collect::<Option<Vec<_>>>(){
+            Some(months) => format!(
+                "debt free in {}{}{}", posc, month_after(months.into_iter().max().unwrap_or(0)), textc
+            ),
+            None => format!("{}not debt free{}", negc, textc),
+        };
+        println!("    {}Total interest {}{}{}, {}", textc, negc, val(total), textc, free);
+    }
+    let total = |plan: &Plan| plan.interest.iter().sum::<f32>();
+    let saved = total(&plans[1].1) - total(&plans[0].1);
+    if saved >= 0.005{
+        println!("  {}Avalanche saves {}{}{} interest", textc, posc, val(saved), textc);
+    } else if saved <= -0.005{
+        println!("  {}Snowball saves {}{}{} interest", textc, posc, val(-saved), textc);
+    }
+
+    let (_, plan) = plans.iter().find(|(s, _)| *s == strategy).expect("Both strategies are planned");
+    let label = format!("{:?}", strategy).to_lowercase();
+    println!("{}{b}Schedule{r} ({}, payment and balance after):", infoc, label);
+    for (month, payments) in plan.schedule.iter().enumerate(){
+        let parts = debts.iter().zip(payments).filter(|(_, (pay, _))| *pay >= 0.005)
+            .map(|(debt, (pay, balance))| format!("{}{}{} {}{}{} ({}{}{})",
+                namec, debt.name, textc, posc, val(*pay), textc, negc, val(*balance), textc
+            ))
+            .collect::<Vec<_>>();
+        println!("  {}{}: {}", textc, month_after(month + 1), parts.join(", "));
+    }
+
+    if draw_graph{
+        let header = debts.iter().map(|debt| debt.name.clone()).collect::<Vec<_>>();
+        let mut rows = vec![(month_after(0), debts.iter().map(|debt| Some(debt.balance)).collect())];
+        rows.extend(plan.schedule.iter().enumerate().map(|(month, payments)|
+            (month_after(month + 1), payments.iter().map(|(_, balance)| Some(*balance)).collect())
+        ));
+        let colours = get_graph_colours(args);
+        line_chart(
+            &format!("Debt payoff ({})", label), &header, &rows, None, &colours, &args.get_string("browser")
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::core::*;
+
+use std::collections::BTreeMap;
+
+use zen_colour::*;
+
+/// Transactions of one command: how many, what they changed (amount and worth for assets)
+type Changes = BTreeMap<&'static str, (usize, f32, f32)>;
+
+/// Balances of every account and asset at the end of two dates,
+/// with the transactions in between that changed them grouped by command
+pub fn diff(nb: &NameBank, ts: &[Trans], from: Date, to: Date, redact: bool){
+    let (textc, infoc, namec, posc, negc, b, r) = (DEFAULT, MAGENTA, BLUE, GREEN, RED, BOLD, RESET);
+    let pncol = |v: f32| if v < 0.0 { negc } else { posc };
+    // asset amounts can be small, like some bitcoin
+    let val = |v: f32, amount: bool| if amount && v.abs() < 1.0{
+        (v * 1000000.0).round() / 1000000.0
+    } else {
+        (v * 100.0).round() / 100.0
+    };
+    let (from, to) = if date_sum(from) <= date_sum(to) { (from, to) } else { (to, from) };
+
+    let mut state = State::new(nb);
+    let worths = |state: &State| state.asset_amounts.iter().zip(&state.asset_prices)
+        .map(|(amount, price)| amount * price).collect::<Vec<_>>();
+    let mut at_from = None;
+    let mut accounts: Vec<Changes> = vec![BTreeMap::new(); state.accounts.len()];
+    let mut assets: Vec<Changes> = vec![BTreeMap::new(); state.asset_amounts.len()];
+    for trans in ts{
+        let day = date_sum(trans.date());
+        if day > date_sum(to){ break; }
+        if day > date_sum(from) && at_from.is_none(){
+            at_from = Some((state.rollup(&state.accounts), state.asset_amounts.clone(), worths(&state)));
+        }
+        let before = (state.rollup(&state.accounts), state.asset_amounts.clone(), worths(&state));
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        if at_from.is_none() { continue; }
+        let balances = state.rollup(&state.accounts);
+        for (id, changes) in accounts.iter_mut().enumerate(){
+            let change = balances[id] - before.0[id];
+            if change.abs() >= 0.005{
+                let entry = changes.entry(trans.command()).or_insert((0, 0.0, 0.0));
+                entry.0 += 1;
+                entry.1 += change;
+            }
+        }
+        let after_worths = worths(&state);
+        for (id, changes) in assets.iter_mut().enumerate(){
+            let amount = state.asset_amounts[id] - before.1[id];
+            let worth = after_worths[id] - before.2[id];
+            if amount.abs() >= 0.000001 || worth.abs() >= 0.005{
+                let entry = changes.entry(trans.command()).or_insert((0, 0.0, 0.0));
+                entry.0 += 1;
+                entry.1 += amount;
+                entry.2 += worth;
+            }
+        }
+    }
+    let at_to = (state.rollup(&state.accounts), state.asset_amounts.clone(), worths(&state));
+    let at_from = at_from.unwrap_or_else(|| at_to.clone());
+
+    let change = |old: f32, new: f32, amount: bool| format!("{}{}{} -> {}{}{} ({}{:+}{})",
+        pncol(old), val(old, amount), textc, pncol(new), val(new, amount), textc,
+        pncol(new - old), val(new - old, amount), textc
+    );
+    let count = |n: usize| format!("{} transaction{}", n, if n == 1 { "" } else { "s" });
+    println!("{}{b}From {} to {}{r}:", infoc, format_date(from), format_date(to));
+    if !redact{
+        println!("  {}Net: {}", textc, change(at_from.0[NET], at_to.0[NET], false));
+    }
+
+    println!("{}{b}Accounts{r}:", infoc);
+    for (id, depth) in nb.account_tree(){
+        if id < NR_BUILDIN_ACCOUNTS || is_ratio(id, nb) { continue; }
+        let (old, new) = (at_from.0[id], at_to.0[id]);
+        if accounts[id].is_empty() && old.abs() < 0.005 && new.abs() < 0.005 { continue; }
+        let indent = "  ".repeat(depth + 1);
+        let name = nb.account_name(id);
+        let name = name.rsplit(':').next().unwrap_or(&name);
+        if redact{
+            println!("{}{}{}{}", indent, namec, name, textc);
+        } else {
+            println!("{}{}{}{}: {}", indent, namec, name, textc, change(old, new, false));
+        }
+        for (cmd, (n, amount, _)) in &accounts[id]{
+            let amount = if redact{
+                String::new()
+            } else {
+                format!(", {}{:+}{}", pncol(*amount), val(*amount, false), textc)
+            };
+            println!("{}  {}: {}{}", indent, cmd, count(*n), amount);
+        }
+    }
+
+    println!("{}{b}Assets{r}:", infoc);
+    for (id, changes) in assets.iter().enumerate(){
+        let (old, new) = (at_from.1[id], at_to.1[id]);
+        let empty = changes.is_empty() && old.abs() < 0.000001 && new.abs() < 0.000001;
+        // the fiat on its way to assets is not something you own
+        if id == FIAT || empty { continue; }
+        if redact{
+            println!("  {}{}{}", namec, nb.asset_name(id), textc);
+        } else {
+            println!("  {}{}{}: {}, worth {}", namec, nb.asset_name(id), textc,
+                change(old, new, true), change(at_from.2[id], at_to.2[id], false)
+            );
+        }
+        for (cmd, (n, amount, worth)) in changes{
+            let amounts = if redact{
+                String::new()
+            } else {
+                format!(", {}{:+}{}, worth {}{:+}{}",
+                    pncol(*amount), val(*amount, true), textc, pncol(*worth), val(*worth, false), textc
+                )
+            };
+            println!("    {}: {}{}", cmd, count(*n), amounts);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::core::*;
+
+use zen_colour::*;
+
+/// Money moved the same way in every one of the last months: src, dst, subtracted, added
+pub type Recurring = (usize, usize, f32, f32);
+
+/// Months before the last one of the history an item has to happen in to be recurring,
+/// the last month is left out as it is often not over yet
+const RECURRING_MONTHS: usize = 3;
+
+/// Money moved between the same accounts with the same amounts in each of the last full months
+fn recurring(nb: &NameBank, ts: &[Trans], last: MonthDate) -> Vec<Recurring>{
+    let mut months = Vec::new();
+    let mut month = last;
+    for _ in 0..RECURRING_MONTHS{
+        month = if month.0 == 1 { (12, month.1 - 1) } else { (month.0 - 1, month.1) };
+        months.push(month);
+    }
+    let cents = |v: f32| (v * 100.0).round() as i64;
+    let mut state = State::new(nb);
+    state.transfer_log = Some(Vec::new());
+    let mut seen: Vec<(MonthDate, (usize, usize, i64, i64))> = Vec::new();
+    for trans in ts{
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        let log = state.transfer_log.replace(Vec::new()).unwrap_or_default();
+        let date = trans.date();
+        if !months.contains(&(date.1, date.2)) { continue; }
+        seen.extend(log.into_iter()
+            .map(|(src, dst, sub, add)| ((date.1, date.2), (src, dst, cents(sub), cents(add)))));
+    }
+    let mut items = seen.iter().map(|(_, item)| *item).collect::<Vec<_>>();
+    items.sort();
+    items.dedup();
+    items.into_iter()
+        .filter(|item| months.iter().all(|month| seen.contains(&(*month, *item))))
+        .map(|(src, dst, sub, add)| (src, dst, sub as f32 / 100.0, add as f32 / 100.0))
+        .collect()
+}
+
+/// Frames for the months after the history: the recurring items keep happening, `_net` changes with
+/// the average spending and receiving of the past year and `ass` accounts grow with a yearly return in %.
+/// Accounts only change by the recurring items and returns, `_net` by the whole average and the returns:
+/// recurring items from or to null are part of the average, so they are not counted twice.
+pub fn forecast(
+    nb: &NameBank, state: &State, ts: &[Trans], hist: &[Vec<f32>], last: MonthDate, months: usize,
+    roi: f32) -> (Vec<Vec<f32>>, Vec<Recurring>)
+{
+    let mut frame = match hist.last(){
+        Some(frame) => frame.clone(),
+        None => return (Vec::new(), Vec::new()),
+    };
+    let recurring = recurring(nb, ts, last);
+    // the last month is left out like for the recurring items, unless it is all there is
+    let recent = if hist.len() > 1{
+        &hist[hist.len().saturating_sub(13)..hist.len() - 1]
+    } else {
+        hist
+    };
+    let window = recent.len() as f32;
+    let spending = recent.iter().map(|frame| frame[SPENDING_MONTH]).sum::<f32>() / window;
+    let receiving = recent.iter().map(|frame| frame[RECEIVING_MONTH]).sum::<f32>() / window;
+    let growth = (1.0 + roi / 100.0).powf(1.0 / 12.0) - 1.0;
+    let mut frames = Vec::with_capacity(months);
+    for _ in 0..months{
+        // null only counts what left and entered the accounts
+        for (src, dst, sub, add) in &recurring{
+            if *src != NULL { frame[*src] -= sub; }
+            if *dst != NULL { frame[*dst] += add; }
+        }
+        let mut gains = 0.0;
+        for (id, balance) in frame.iter_mut().enumerate().skip(NR_BUILDIN_ACCOUNTS){
+            if state.account_labels[id] == AccountLabel::Assets{
+                let gain = *balance * growth;
+                *balance += gain;
+                gains += gain;
+            }
+        }
+        frame[NET] += receiving - spending + gains;
+        frame[SPENDING_MONTH] = spending;
+        frame[RECEIVING_MONTH] = receiving;
+        frame[SPENDING_CUMULATIVE] += spending;
+        frame[RECEIVING_CUMULATIVE] += receiving;
+        frame[SPENDING_12M] = spending * 12.0;
+        frame[RECEIVING_12M] = receiving * 12.0;
+        frame[SAVING_RATE_12M] = if receiving != 0.0{
+            (receiving - spending) / receiving * 100.0
+        } else {
+            0.0
+        };
+        frame[NET_MONTHS] = if spending != 0.0 { frame[NET] / spending } else { 0.0 };
+        frames.push(frame.clone());
+    }
+    (frames, recurring)
+}
+
+/// Where `_net` and the graph accounts end up and the recurring items the forecast uses
+pub fn print_forecast(
+    nb: &NameBank, hist: &[Vec<f32>], frames: &[Vec<f32>], recurring: &[Recurring], includes: &[String],
+    redact: bool)
+{
+    let (textc, infoc, namec, posc, negc, b, r) = (DEFAULT, MAGENTA, BLUE, GREEN, RED, BOLD, RESET);
+    let pncol = |v: f32| if v < 0.0 { negc } else { posc };
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+    let (now, then) = match (hist.last(), frames.last()){
+        (Some(now), Some(then)) => (nb.rollup(now), nb.rollup(then)),
+        _ => return,
+    };
+    println!("{}{b}Forecast{r} ({} months):", infoc, frames.len());
+    if redact{
+        let change = if now[NET] != 0.0 { (then[NET] / now[NET] - 1.0) * 100.0 } else { 0.0 };
+        println!("  {}_net: {}{:+}%{}", namec, pncol(change), val(change), textc);
+        return;
+    }
+    let mut ids = vec![NET];
+    ids.extend(includes.iter().filter_map(|name| nb.find_account(name)).filter(|id| *id != NET));
+    for id in ids{
+        println!("  {}{}{}: {}{}{} -> {}{}{}",
+            namec, nb.account_name(id), textc, pncol(now[id]), val(now[id]), textc,
+            pncol(then[id]), val(then[id]), textc
+        );
+    }
+    if !recurring.is_empty(){
+        println!("  {}Recurring every month:", textc);
+        for (src, dst, sub, add) in recurring{
+            let amount = if (sub - add).abs() < 0.005{
+                val(*sub).to_string()
+            } else {
+                format!("{} ({})", val(*sub), val(*add))
+            };
+            println!("    {}{}{} -> {}{}{}: {}",
+                namec, nb.account_name(*src), textc, namec, nb.account_name(*dst), textc, amount
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::core::*;
+
+use std::fs;
+
+use term_basics_linux as tbl;
+
+enum Line{
+    Blank,
+    Comment(String),
+    Entry(Date, Vec<String>),
+}
+
+/// Rewrite the ledger in a canonical form: explicit dates, uniform numbers and aligned columns.
+/// Only writes the file when the formatted ledger parses into the exact same transactions.
+pub fn format_ledger(file: &str, contents: &str, nb: &NameBank, ts: &[Trans], sort: bool){
+    let mut date = Date::default();
+    let mut lines = contents.split('\n').map(|line| {
+        let line = line.trim();
+        if line.is_empty(){
+            return Line::Blank;
+        }
+        if line.starts_with('#'){
+            return Line::Comment(line.to_string());
+        }
+        let mut fields = line.split(',').map(|field| field.trim().to_string()).collect::<Vec<_>>();
+        if fields.len() > 1 && !DATELESS_COMMANDS.contains(&fields[0].as_str()){
+            if fields[1] != "_"{
+                if let Some(parsed) = parse_date(&fields[1]){
+                    date = parsed;
+                }
+            }
+            fields[1] = format_date(date);
+        }
+        for (i, (_, kind)) in command_fields(&fields[0]).unwrap_or(&[]).iter().enumerate(){
+            let field = match fields.get_mut(i + 2){
+                Some(field) => field,
+                None => break,
+            };
+            let formatted = match kind{
+                FieldKind::Amount => tbl::string_to_value::<f64>(field).map(|v| format!("{}", v)),
+                FieldKind::Number => tbl::string_to_value::<f32>(field).map(|v| format!("{}", v)),
+                FieldKind::Parts => Some(field.split(';').map(|part| match part.split_once('='){
+                    Some((dst, amount)) => {
+                        let amount = amount.trim();
+                        let amount = tbl::string_to_value::<f64>(amount)
+                            .map(|v| format!("{}", v)).unwrap_or(amount.to_string());
+                        format!("{}={}", dst.trim(), amount)
+                    },
+                    None => part.trim().to_string(),
+                }).collect::<Vec<_>>().join(";")),
+                _ => None,
+            };
+            if let Some(formatted) = formatted{
+                *field = formatted;
+            }
+        }
+        Line::Entry(date, fields)
+    }).collect::<Vec<_>>();
+    // a trailing newline splits into a last empty line, keep it there
+    let trailing = matches!(lines.last(), Some(Line::Blank)) && contents.ends_with('\n');
+    if trailing{
+        lines.pop();
+    }
+
+    if sort{
+        // comments and blank lines stick to the entry below them
+        let mut groups = Vec::new();
+        let mut group = Vec::new();
+        for line in lines{
+            let key = if let Line::Entry(date, _) = &line { Some(date_sum(*date)) } else { None };
+            group.push(line);
+            if let Some(key) = key{
+                groups.push((key, std::mem::take(&mut group)));
+            }
+        }
+        groups.sort_by_key(|(key, _)| *key);
+        lines = groups.into_iter().flat_map(|(_, group)| group).chain(group).collect();
+    }
+
+    let mut widths = Vec::new();
+    for line in &lines{
+        if let Line::Entry(_, fields) = line{
+            for (i, field) in fields.iter().enumerate().take(fields.len() - 1){
+                if widths.len() <= i { widths.push(0); }
+                widths[i] = widths[i].max(field.chars().count());
+            }
+        }
+    }
+    let mut out = lines.iter().map(|line| match line{
+        Line::Blank => String::new(),
+        Line::Comment(comment) => comment.clone(),
+        Line::Entry(_, fields) => {
+            let mut string = String::new();
+            for (i, field) in fields.iter().enumerate(){
+                if i == fields.len() - 1{
+                    string.push_str(field);
+                } else {
+                    // the comma right after the field, padding before the next one
+                    string.push_str(&format!("{:<w$}", format!("{},", field), w = widths[i] + 2));
+                }
+            }
+            string
+        },
+    }).collect::<Vec<_>>().join("\n");
+    if trailing{
+        out.push('\n');
+    }
+
+    // same ids for the same names, but variables, transaction ids and closings have to be defined again
+    let mut check_nb = nb.clone();
+    check_nb.clear_definitions();
+    let mut check_date = Date::default();
+    let formatted = out.split('\n').filter_map(|line|
+        line.to_string().into_trans(&mut check_nb, &mut check_date)
+    ).collect::<Vec<_>>();
+    let mut expected = ts.iter().collect::<Vec<_>>();
+    if sort{
+        expected.sort_by_key(|trans| date_sum(trans.date()));
+    }
+    let identical = formatted.len() == expected.len() && formatted.iter().zip(expected)
+        .all(|(res, trans)| matches!(res, Ok(formatted) if formatted == trans));
+    if !identical{
+        println!("Formatting would change the transactions, {} is left untouched.", file);
+        return;
+    }
+    fs::write(file, out).expect("Could not write to file!");
+}
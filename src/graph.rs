@@ -48,12 +48,74 @@ pub fn get_graph_colours(args: &lapp::Args) -> Vec<String>{
 
 #[allow(clippy::too_many_arguments)]
 pub fn graph(
-    norm_fac: f32, nb: &NameBank, ts: &[Trans], include: &[&str],
+    norm_fac: f32, nb: &NameBank, hist: &[Vec<f32>], start_date: MonthDate, include: &[&str],
     redact_map: &HashMap<String, String>, colours: Vec<String>,
-    browser: &str, year_digits: u16, use_month_names: bool)
+    browser: &str, year_digits: u16, use_month_names: bool, forecast_from: Option<usize>)
+{
+    let mut header = Vec::new();
+    let mut indices = Vec::new();
+    (0..nb.next_account_id()).for_each(|id| {
+        let name = nb.account_name(id);
+        if include.contains(&&name[..]){
+            let name = if let Some(redacted) = redact_map.get(&name){
+                redacted.to_string()
+            } else {
+                name
+            };
+            header.push(name);
+            indices.push(id);
+        }
+    });
+    let mut rows = Vec::new();
+    let mut date = start_date;
+    for bs in hist{
+        let format_date = |mm, yy| {
+            let m = if use_month_names{
+                match mm{
+                    1 => "Jan",
+                    2 => "Feb",
+                    3 => "Mar",
+                    4 => "Apr",
+                    5 => "May",
+                    6 => "Jun",
+                    7 => "Jul",
+                    8 => "Aug",
+                    9 => "Sep",
+                    10 => "Oct",
+                    11 => "Nov",
+                    12 => "Dec",
+                    _ => "AAA"
+                }.to_string()
+            } else {
+                format!("{}", mm)
+            };
+            let y = format!("{}", yy).chars().rev().take(year_digits as usize).collect::<String>().chars().rev().collect::<String>();
+            format!("{} {}", m, y)
+        };
+        let label = format_date(date.0, date.1);
+        let frame_date = date;
+        date = next_month(date);
+        let bs = nb.rollup(bs);
+        let mut values = Vec::new();
+        for ind in &indices{
+            // closed accounts stop at the month they are closed in
+            let closed = nb.account_closed(*ind)
+                .map(|(_, m, y)| (y, m) < (frame_date.1, frame_date.0))
+                .unwrap_or(false);
+            let value = if is_ratio(*ind, nb) { bs[*ind] } else { bs[*ind] / norm_fac };
+            values.push(if closed { None } else { Some(value) });
+        }
+        rows.push((label, values));
+    }
+    line_chart("Net worth", &header, &rows, forecast_from, &colours, browser);
+}
+
+/// Draw lines in graph.html and open it: every row is a label with a value per line,
+/// lines without a value in a row have a gap there. Lines are dashed from the forecast row on.
+pub fn line_chart(
+    title: &str, header: &[String], rows: &[(String, Vec<Option<f32>>)], forecast_from: Option<usize>,
+    colours: &[String], browser: &str)
 {
-    let mut state = State::new(nb);
-    let (hist, start_date) = hist(&mut state, ts);
     let mut page = String::new();
     let mut carray = String::new();
     carray.push('[');
@@ -76,7 +138,7 @@ pub fn graph(
                 ]);
                 var options = {{
                     titleColor: '{}',
-                    title: 'Net worth',
+                    title: '{}',
                     backgroundColor: '{}',
                     lineWidth: 5,
                     legend: {{
@@ -95,59 +157,29 @@ pub fn graph(
     <body style=\"background: {};\">
         <div id=\"line_chart\" style=\"width: 100%; height: 100%; background: {};\"></div>
     </body>
-</html>", colours[1], colours[0], colours[1], carray, colours[1], colours[1], colours[0], colours[0]);
-//         println!("{}", tail);
+</html>", colours[1], title, colours[0], colours[1], carray, colours[1], colours[1], colours[0], colours[0]);
     page.push_str(head);
     page.push('[');
     page.push_str("\'Date\',");
-    let mut indices = Vec::new();
-    (0..nb.next_account_id()).for_each(|id| {
-        let name = nb.account_name(id);
-        if include.contains(&&name[..]){
-            let name = if let Some(redacted) = redact_map.get(&name){
-                redacted.to_string()
-            } else {
-                name
-            };
-            page.push_str(&format!("\'{}\',", name));
-            indices.push(id);
+    for name in header{
+        page.push_str(&format!("\'{}\',", name));
+        if forecast_from.is_some(){
+            page.push_str("{role: \'certainty\'},");
         }
-    });
+    }
     page.push_str("],\n");
-    let mut date = start_date;
-    for bs in hist.into_iter(){
-        let format_date = |mm, yy| {
-            let m = if use_month_names{
-                match mm{
-                    1 => "Jan",
-                    2 => "Feb",
-                    3 => "Mar",
-                    4 => "Apr",
-                    5 => "May",
-                    6 => "Jun",
-                    7 => "Jul",
-                    8 => "Aug",
-                    9 => "Sep",
-                    10 => "Oct",
-                    11 => "Nov",
-                    12 => "Dec",
-                    _ => "AAA"
-                }.to_string()
-            } else {
-                format!("{}", mm)
-            };
-            let y = format!("{}", yy).chars().rev().take(year_digits as usize).collect::<String>().chars().rev().collect::<String>();
-            format!("\'{} {}\',", m, y)
-        };
+    for (i, (label, values)) in rows.iter().enumerate(){
         page.push('[');
-        page.push_str(&format_date(date.0, date.1));
-        date = if date.0 == 12{
-            (1, date.1 + 1)
-        } else {
-            (date.0 + 1, date.1)
-        };
-        for ind in &indices{
-            page.push_str(&format!("{},", (bs[*ind] / norm_fac)));
+        page.push_str(&format!("\'{}\',", label));
+        let certain = forecast_from.map(|from| i < from);
+        for value in values{
+            match value{
+                Some(value) => page.push_str(&format!("{},", value)),
+                None => page.push_str("null,"),
+            }
+            if let Some(certain) = certain{
+                page.push_str(&format!("{},", certain));
+            }
         }
         page.push_str("],\n");
     }
@@ -156,4 +188,3 @@ pub fn graph(
     file.write_all(page.as_bytes()).expect("Could not write to file!");
     Command::new(browser).arg("graph.html").output().unwrap_or_else(|_| panic!("Could not open graph in {}!", browser));
 }
-
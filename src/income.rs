@@ -0,0 +1,83 @@
+use crate::core::*;
+
+use zen_colour::*;
+
+/// Income of every asset with inc lines in total and over the past 12 months,
+/// as a part of what the asset cost and of what it is worth now
+pub fn income(nb: &NameBank, ts: &[Trans], redact: bool){
+    let (textc, infoc, namec, posc, fracc, b, r) = (DEFAULT, MAGENTA, BLUE, GREEN, YELLOW, BOLD, RESET);
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+
+    let mut state = State::new(nb);
+    state.income_log = Some(Vec::new());
+    let assets = state.asset_amounts.len();
+    // average cost, amounts bought before the asset has a price are costed at its first price
+    let mut cost = vec![0.0; assets];
+    let mut unpriced = vec![0.0; assets];
+    let mut payouts = Vec::new(); // day, asset, amount
+    let mut day = 0;
+    for trans in ts{
+        let before = state.asset_amounts.clone();
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        if trans.date().2 != 0{
+            day = date_days(trans.date());
+        }
+        let log = state.income_log.replace(Vec::new()).unwrap_or_default();
+        payouts.extend(log.into_iter().map(|(asset, _, amount)| (day, asset, amount)));
+        for asset in 0..assets{
+            let (old, new, price) = (before[asset], state.asset_amounts[asset], state.asset_prices[asset]);
+            if new > old{
+                if price == 0.0 { unpriced[asset] += new - old; } else { cost[asset] += (new - old) * price; }
+            } else if new < old && old > 0.0{
+                let kept = (new / old).max(0.0);
+                cost[asset] *= kept;
+                unpriced[asset] *= kept;
+            }
+            if unpriced[asset] > 0.0 && price != 0.0{
+                cost[asset] += unpriced[asset] * price;
+                unpriced[asset] = 0.0;
+            }
+        }
+    }
+    if payouts.is_empty(){
+        println!("There is no income, add inc lines for what your assets pay out.");
+        return;
+    }
+
+    let mut totals = vec![(0.0, 0.0); assets]; // past 12 months, all time
+    for (paid, asset, amount) in &payouts{
+        if *paid > day - 365{
+            totals[*asset].0 += amount;
+        }
+        totals[*asset].1 += amount;
+    }
+    let pct = |part: f32, whole: f32| if whole > 0.0{
+        format!("{}{}%{}", fracc, val(part / whole * 100.0), textc)
+    } else {
+        String::from("-")
+    };
+    println!("{}{b}Income{r} (past 12 months, total, yield on cost, current yield):", infoc);
+    let (mut all_ttm, mut all_total, mut all_cost, mut all_worth) = (0.0, 0.0, 0.0, 0.0);
+    for (asset, (ttm, total)) in totals.into_iter().enumerate(){
+        if !payouts.iter().any(|(_, a, _)| *a == asset){ continue; }
+        let worth = state.asset_amounts[asset].max(0.0) * state.asset_prices[asset];
+        all_ttm += ttm;
+        all_total += total;
+        all_cost += cost[asset];
+        all_worth += worth;
+        let amounts = if redact{
+            String::new()
+        } else {
+            format!("{}{}{}, {}{}{}, ", posc, val(ttm), textc, posc, val(total), textc)
+        };
+        println!("  {}{}{}: {}{}, {}",
+            namec, nb.asset_name(asset), textc, amounts, pct(ttm, cost[asset]), pct(ttm, worth)
+        );
+    }
+    let amounts = if redact{
+        String::new()
+    } else {
+        format!("{}{}{}, {}{}{}, ", posc, val(all_ttm), textc, posc, val(all_total), textc)
+    };
+    println!("  {}All: {}{}, {}", textc, amounts, pct(all_ttm, all_cost), pct(all_ttm, all_worth));
+}
@@ -0,0 +1,206 @@
+use crate::core::*;
+
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+
+use term_basics_linux as tbl;
+use zen_colour::*;
+
+/// Walk through a new transaction field by field and append it to the file when confirmed.
+pub fn add_interactive(file: &str, contents: &str, mut nb: NameBank, ts: &[Trans], date: Date){
+    let (infoc, namec, posc, negc, b, r) = (MAGENTA, BLUE, GREEN, RED, BOLD, RESET);
+    let commands = COMMANDS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    let mut accounts = vec![nb.account_name(NULL)];
+    accounts.append(&mut nb.user_account_names());
+    let assets = nb.asset_names();
+    let periods = PERIODS.iter().map(|(name, _)| name.to_string()).collect::<Vec<_>>();
+    let mut before = State::new(&nb);
+    update(ts, &mut before, None, None);
+
+    println!("{b}{}New transaction{r} (tab and enter to complete, arrows to scroll)", infoc);
+    let cmd = loop{
+        let cmd = prompt_completed("command", &commands);
+        if commands.contains(&cmd) { break cmd; }
+        println!("    {}{}{}", negc, TransErr::UnknownCommand(cmd), r);
+    };
+    let date_string = prompt_date(date);
+    let mut fields = vec![cmd.clone(), date_string];
+    for (field, kind) in command_fields(&cmd).unwrap_or(&[]){
+        fields.push(match kind{
+            FieldKind::Account => prompt_completed(field, &accounts),
+            FieldKind::OptionalAccount => prompt_completed_optional(field, &accounts),
+            FieldKind::Asset => prompt_completed(field, &assets),
+            FieldKind::Amount => prompt_amount(field, &before, false),
+            FieldKind::Number => prompt_amount(field, &before, true),
+            FieldKind::Parts => prompt_parts(&accounts, &before),
+            FieldKind::Id => prompt_completed(field, &nb.reversible_ids()),
+            FieldKind::Period => prompt_completed(field, &periods),
+            FieldKind::Name => prompt_completed(field, &assets),
+            FieldKind::Text => prompt_text(field),
+        });
+    }
+    print!("  tags (comma separated, @name to give an id, ! pending, * cleared): ");
+    let tags = tbl::input_field_simple(true);
+    let tags = tags.split(',').map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty())
+        .collect::<Vec<_>>();
+    // optional fields left empty are left out at the end of the line and written as _ before tags
+    let kinds = command_fields(&cmd).unwrap_or(&[]);
+    for (field, (_, kind)) in fields.iter_mut().skip(2).zip(kinds){
+        if *kind == FieldKind::OptionalAccount && field.is_empty(){
+            *field = String::from("_");
+        }
+    }
+    if tags.is_empty(){
+        while fields.len() > 2 && fields.last().map(String::as_str) == Some("_"){
+            fields.pop();
+        }
+    }
+    fields.extend(tags);
+    let line = fields.join(",");
+
+    let mut new_date = date;
+    let trans = match line.clone().into_trans(&mut nb, &mut new_date){
+        Some(Ok(trans)) => trans,
+        Some(Err(err)) => {
+            println!("{}{}{}", negc, err, r);
+            return;
+        },
+        None => return,
+    };
+    if date_sum(new_date) < date_sum(date){
+        println!("{}{}{}", negc, TransErr::OrderError(new_date), r);
+        return;
+    }
+
+    // the new line can introduce new names
+    let mut before = State::new(&nb);
+    update(ts, &mut before, None, None);
+    let mut after = State::new(&nb);
+    update(ts, &mut after, None, None);
+    update(std::slice::from_ref(&trans), &mut after, None, None);
+
+    println!("{b}{}Line{r}: {}", infoc, line);
+    if !trans.tags().is_empty(){
+        let tags = trans.tags().iter().map(|tag| nb.tag_name(*tag)).collect::<Vec<_>>();
+        println!("{b}{}Tags{r}: {}", infoc, tags.join(", "));
+    }
+    println!("{b}{}Changes{r}:", infoc);
+    let pncol = |v: f32| if v < 0.0 { negc } else { posc };
+    let changes = into_named_accounts(&before.accounts, &nb, &before).into_iter()
+        .zip(into_named_accounts(&after.accounts, &nb, &after))
+        .map(|((name, old, _), (_, new, _))| (name, old, new))
+        .chain(
+            into_named_assets(&before.asset_amounts, &nb).into_iter()
+                .zip(into_named_assets(&after.asset_amounts, &nb))
+                .map(|((name, old), (_, new))| (name, old, new))
+        );
+    for (name, old, new) in changes{
+        if old == new { continue; }
+        println!("  {}{}{}: {}{}{} -> {}{}{} ({}{:+}{})",
+            namec, name, r, pncol(old), old, r, pncol(new), new, r, pncol(new - old), new - old, r
+        );
+    }
+
+    print!("Append to {}? (y/n): ", file);
+    if !tbl::string_to_bool(&tbl::input_field_simple(true)){
+        return;
+    }
+    let mut out = OpenOptions::new().append(true).open(file).expect("Could not open file!");
+    if !contents.is_empty() && !contents.ends_with('\n'){
+        out.write_all(b"\n").expect("Could not write to file!");
+    }
+    out.write_all(format!("{}\n", line).as_bytes()).expect("Could not write to file!");
+}
+
+fn prompt_completed(field: &str, options: &[String]) -> String{
+    loop{
+        let input = prompt_completed_optional(field, options);
+        if !input.is_empty() { return input; }
+    }
+}
+
+/// Like prompt_completed but an empty input is accepted
+fn prompt_completed_optional(field: &str, options: &[String]) -> String{
+    let mut candidates = options.to_vec();
+    loop{
+        let mut ilist = tbl::InputList::new(candidates.len());
+        candidates.iter().for_each(|candidate| ilist.add(candidate));
+        print!("  {}: ", field);
+        let input = tbl::input_field_scrollable(&mut ilist, true);
+        if !input.contains('\t'){
+            let input = input.trim().to_string();
+            if !input.is_empty() && !options.contains(&input){
+                println!("    {}new: {}{}", YELLOW, input, RESET);
+            }
+            return input;
+        }
+        let prefix = input.replace('\t', "");
+        let prefix = prefix.trim();
+        let matches = options.iter().filter(|option| option.starts_with(prefix))
+            .cloned().collect::<Vec<_>>();
+        match matches.len(){
+            0 => println!("    no completions for '{}'", prefix),
+            1 => {
+                println!("    {}", matches[0]);
+                return matches[0].clone();
+            },
+            _ => {
+                println!("    {}", matches.join(" "));
+                candidates = matches;
+            },
+        }
+    }
+}
+
+/// Destinations with their amounts until an empty destination, then the optional rest destination
+fn prompt_parts(accounts: &[String], state: &State) -> String{
+    let mut parts = Vec::new();
+    loop{
+        let dst = prompt_completed_optional("part dst (empty when done)", accounts);
+        if dst.is_empty() { break; }
+        let amount = prompt_amount("part amount", state, false);
+        parts.push(format!("{}={}", dst, amount));
+    }
+    let rest = prompt_completed_optional("rest dst (empty for none)", accounts);
+    if !rest.is_empty(){
+        parts.push(rest);
+    }
+    parts.join(";")
+}
+
+/// Free text that can be empty, a comma would end the field
+fn prompt_text(field: &str) -> String{
+    print!("  {}: ", field);
+    tbl::input_field_simple(true).replace(',', " ").trim().to_string()
+}
+
+fn prompt_date(default: Date) -> String{
+    loop{
+        print!("  date ({}): ", format_date(default));
+        let input = tbl::input_field_simple(true).trim().to_string();
+        if input.is_empty(){
+            return format_date(default);
+        }
+        if parse_date(&input).is_some(){
+            return input;
+        }
+        println!("    {}{}{}", RED, TransErr::DateFields, RESET);
+    }
+}
+
+/// Amounts can use variables and balances, previewed with the balances at the end of the file
+fn prompt_amount(field: &str, state: &State, plain: bool) -> String{
+    loop{
+        print!("  {}: ", field);
+        let input = tbl::input_field_simple(true).trim().to_string();
+        match state.eval(&input){
+            Ok(ans) => {
+                println!("    = {}", ans);
+                return if plain { format!("{}", ans) } else { input };
+            },
+            Err(err) => println!("    {}{}{}",
+                RED, TransErr::FloatError(field.to_string(), input, format!("{}", err)), RESET
+            ),
+        }
+    }
+}
@@ -1,17 +1,61 @@
 mod core;
 mod summary;
 mod graph;
+mod interactive;
+mod format;
+mod reconcile;
+mod returns;
+mod simulate;
+mod breakdown;
+mod sankey;
+mod debt;
+mod rebalance;
+mod income;
+mod tax;
+mod diff;
+mod forecast;
+mod anomalies;
 
 use crate::core::*;
 use crate::summary::*;
 use crate::graph::*;
+use crate::interactive::*;
+use crate::format::*;
+use crate::reconcile::*;
+use crate::returns::*;
+use crate::simulate::*;
+use crate::breakdown::*;
+use crate::sankey::*;
+use crate::debt::*;
+use crate::rebalance::*;
+use crate::income::*;
+use crate::tax::*;
+use crate::diff::*;
+use crate::forecast::*;
+use crate::anomalies::*;
 
 use std::fs;
+use std::env;
 use std::collections::HashMap;
 
+const MODES: [&str; 14] = [
+    "summary", "add", "fmt", "reconcile", "returns", "simulate", "breakdown", "sankey", "debt",
+    "rebalance", "income", "tax", "diff", "anomalies"
+];
+
 fn main() {
-    let args = lapp::parse_args("
+    // the mode is an optional first word, eg: stonks add file.csv
+    let mode = env::args().nth(1).filter(|arg| MODES.contains(&arg.as_str()));
+    let mut args = lapp::Args::new("
         Personal finance tool using a transactional database approach
+        Usage: stonks [mode] [options] <file>
+        Modes: summary (default), add (interactive entry), fmt (canonicalise file),
+            reconcile (compare an account with a statement), returns (investment performance),
+            simulate (Monte Carlo runway), breakdown (spending and receiving per account and tag),
+            sankey (cash flow diagram), debt (debt payoff planner),
+            rebalance (compare holdings with target allocation), income (what assets pay out),
+            tax (numbers per tax year), diff (what changed between two dates),
+            anomalies (months that spent or received unusually much or little)
         -r, --redact redact absolute valuations
         -g, --graph draw graph
         -p, --palette (default \'\') file to read colours from
@@ -24,12 +68,46 @@ fn main() {
         --date-month-digit use a digit instead of a 3 letter name for a date's month
         --value-rounding (default \'\') whole to round to integers, none to never round
         --min-asset-worth (default 1.0) don't list assets worth less
+        --sort fmt: stable sort entries by date
+        --account (default \'\') reconcile: account to reconcile
+        --balance (default 0.0) reconcile: balance on the statement
+        --at... (string) reconcile: date of the statement, diff: the two dates to compare
+        --paths (default 10000) simulate: number of simulated futures
+        --years (default 50) simulate: years to simulate
+        --roi-mean (default 5.0) simulate, forecast: average yearly return in %
+        --roi-sd (default 15.0) simulate: standard deviation of the yearly return in %
+        --inflation-mean (default 2.5) simulate: average yearly inflation in %
+        --inflation-sd (default 1.5) simulate: standard deviation of the yearly inflation in %
+        --history (default \'\') simulate: file with year,return,inflation lines to draw years from
+        --seed (default 0) simulate: seed for the random numbers, 0 for a random seed
+        --scenarios (string...) runway scenarios eg. name=retire;inflation=3;roi=5;spending=1500;income=300;lump=01/01/2030:-5000
+        --scenario-file (default \'\') file with a runway scenario on every line
+        --yield-rate (default 2.0) yearly yield in % to compare your spending with
+        --years-after (default 24) show durations longer than this many months in years
+        --period (default month) breakdown: month or year
+        --top (default 5) breakdown: number of biggest accounts and tags to show, anomalies: of transactions
+        --baseline (default 6) anomalies: number of months before a month to compare it with
+        --threshold (default 2.0) anomalies: standard deviations a month has to differ from its baseline
+        --from (default \'\') sankey: first date to include
+        --to (default \'\') sankey: last date to include
+        --budget (default 0.0) debt: monthly payments for all debts, 0 for only the minimums
+        --strategy (default avalanche) debt: avalanche or snowball, the one to show the schedule of
+        --cash (default 0.0) rebalance: new money to only buy with instead of selling
+        --tax-year-start (default 1) tax: month the tax year starts in
+        --csv (default \'\') tax: file to also write the report to as CSV
+        --forecast (default 0) months to project _net and the graph accounts forward, dashed in the graph
+        --accrued show the interest of int lines accrued since it was last posted
+        --real show values in the money of one date using the price index from cpi lines
+        --real-base (default \'\') date whose money to show values in, the last date if empty
+        --cpi-file (default \'\') file with date,index lines to use next to the cpi lines
         <file> (string) transactional \"database\" file
-    ");
+    ").start(if mode.is_some() { 2 } else { 1 });
+    args.parse();
+    let mode = mode.unwrap_or_else(|| String::from("summary"));
     let infile = args.get_string("file");
     let redact = args.get_bool("redact");
     let draw_graph = args.get_bool("graph");
-    let contents = fs::read_to_string(infile).expect("Couldn't read sample.");
+    let contents = fs::read_to_string(&infile).expect("Couldn't read sample.");
     let browser = args.get_string("browser");
     let year_digits = args.get_integer("date-year-digits").clamp(0, 4) as u16;
     let use_month_name = !args.get_bool("date-month-digit");
@@ -38,65 +116,296 @@ fn main() {
     let min_asset_worth = args.get_float("min-asset-worth");
     let mut redact_map = HashMap::new();
     for element in redact_list{
-        let split = element.split(':').collect::<Vec<_>>();
-        if split.len() < 2 { continue; }
-        redact_map.insert(split[0].to_string(), split[1].to_string());
+        // account names can contain colons themselves, the redacted name can not
+        if let Some((name, redacted)) = element.rsplit_once(':'){
+            redact_map.insert(name.to_string(), redacted.to_string());
+        }
     }
 
-    let mut namebank = NameBank::new();
-    let mut date = Date::default();
-    let mut prev_date_sum = 0;
-    let mut ts = Vec::new();
-    let mut errs = Vec::new();
-    for (ln, line) in contents.split('\n').enumerate() {
-        let parse_res = line.to_string().into_trans(&mut namebank, &mut date);
-        match parse_res {
-            Some(Err(e)) => errs.push((ln + 1, e)), // lines start at 1, indices at 0
-            Some(Ok(t)) => {
-                let date_sum =
-                    (date.0 as usize) + (date.1 as usize) * 100 + (date.2 as usize) * 10000;
-                if date_sum < prev_date_sum {
-                    errs.push((ln + 1, TransErr::OrderError(date)));
+    let sort = mode == "fmt" && args.get_bool("sort");
+    let (mut namebank, ts, lines, date) = if let Some(parsed) = parse(&contents, !sort){
+        parsed
+    } else {
+        return;
+    };
+
+    if mode == "add"{
+        add_interactive(&infile, &contents, namebank, &ts, date);
+        return;
+    }
+    if mode == "fmt"{
+        format_ledger(&infile, &contents, &namebank, &ts, sort);
+        return;
+    }
+    let (ts, origins, mut accrued) = accrue_interest(ts, lines, &mut namebank);
+
+    let mut state = State::new(&namebank);
+    let (mut hist, start_date) = hist(&mut state, &ts);
+    if !state.errors.is_empty(){
+        println!("The following errors have been found while processing:");
+        for (index, err) in &state.errors{
+            let id = ts[*index].id().map(|id| format!(" @{}", id)).unwrap_or_default();
+            println!("  {}{}:\t{}", origins[*index], id, err);
+        }
+        return;
+    }
+    let real = args.get_bool("real");
+    let mut real_factor = 1.0;
+    let mut real_factors = Vec::new(); // every month of the history
+    let mut real_base = None;
+    if real{
+        let mut cpi = cpi_series(&ts);
+        let cpi_file = args.get_string("cpi-file");
+        if !cpi_file.is_empty(){
+            let contents = fs::read_to_string(&cpi_file).expect("Couldn't read cpi file.");
+            for line in contents.split('\n').map(|line| line.trim()){
+                if line.is_empty() || line.starts_with('#'){ continue; }
+                let index = line.split_once(',')
+                    .and_then(|(date, index)| Some((parse_date(date.trim())?, index.trim().parse().ok()?)));
+                match index{
+                    Some(index) => cpi.push(index),
+                    None => {
+                        println!("Could not parse '{}' in {}.", line, cpi_file);
+                        return;
+                    },
                 }
-                prev_date_sum = date_sum;
-                ts.push(t);
-            },
-            _ => {  },
+            }
+        }
+        if cpi.is_empty(){
+            println!("Real values need a price index, add cpi lines or give a --cpi-file.");
+            return;
         }
+        let base = args.get_string("real-base");
+        let base = if base.is_empty(){
+            date
+        } else if let Some(base) = parse_date(&base){
+            base
+        } else {
+            println!("Could not parse date '{}'.", base);
+            return;
+        };
+        real_base = Some(base);
+        real_factors = deflate(&mut hist, start_date, &cpi, base, &namebank);
+        real_factor = real_factors.last().copied().unwrap_or(1.0);
+        scale_values(&mut state.accounts, real_factor, &namebank);
+        rolling_series(&mut hist, &mut state);
+        state.asset_prices.iter_mut().for_each(|price| *price *= real_factor);
+        accrued.iter_mut().for_each(|amount| *amount *= real_factor);
     }
-    if !errs.is_empty(){
-        println!("The following errors have been found while parsing:");
-        for (line, err) in errs{
-            println!("  {}:\t{}", line, err);
+    if mode == "reconcile"{
+        let at = args.get_strings("at");
+        let at = match at.first().map(|at| parse_date(at)){
+            Some(None) => {
+                println!("Could not parse date '{}'.", at[0]);
+                return;
+            },
+            Some(date) => date,
+            None => None,
+        };
+        reconcile(
+            &namebank, &ts, &origins, &contents, &args.get_string("account"),
+            args.get_float("balance"), at
+        );
+        return;
+    }
+    if mode == "diff"{
+        let mut dates = Vec::new();
+        for at in args.get_strings("at"){
+            match parse_date(&at){
+                Some(date) => dates.push(date),
+                None => {
+                    println!("Could not parse date '{}'.", at);
+                    return;
+                },
+            }
+        }
+        if dates.len() != 2{
+            println!("Give the two dates to compare as --at A --at B.");
+            return;
         }
+        diff(&namebank, &ts, dates[0], dates[1], redact);
         return;
     }
-
-    let mut state = State::new(&namebank);
-    let (hist, _date) = hist(&mut state, &ts);
+    if mode == "tax"{
+        let factors = if real { Some(real_factors.as_slice()) } else { None };
+        tax(&args, &namebank, &state, &ts, &hist, start_date, factors);
+        return;
+    }
+    if mode == "income"{
+        income(&namebank, &ts, redact);
+        return;
+    }
+    if mode == "returns"{
+        returns(&namebank, &ts);
+        return;
+    }
+    if mode == "breakdown"{
+        let by_year = match args.get_string("period").as_str(){
+            "month" => false,
+            "year" => true,
+            period => {
+                println!("Unknown period '{}', use month or year.", period);
+                return;
+            },
+        };
+        breakdown(&namebank, &ts, by_year, args.get_integer("top").max(1) as usize, redact);
+        return;
+    }
+    if mode == "anomalies"{
+        let factors = if real { Some(real_factors.as_slice()) } else { None };
+        anomalies(&args, &namebank, &ts, &hist, start_date, factors);
+        return;
+    }
+    if mode == "sankey"{
+        let (from, to) = match (date_flag(&args, "from"), date_flag(&args, "to")){
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(err), _) | (_, Err(err)) => {
+                println!("{}", err);
+                return;
+            },
+        };
+        sankey(&namebank, &ts, from, to, redact, &get_graph_colours(&args), &browser);
+        return;
+    }
+    if mode == "debt"{
+        debt(&args, &namebank, &state, date, draw_graph);
+        return;
+    }
+    if mode == "rebalance"{
+        rebalance(&namebank, &state, date, args.get_float("cash"), redact);
+        return;
+    }
+    if mode == "simulate"{
+        simulate(&args, &namebank, &state, &hist, draw_graph);
+        return;
+    }
+    let mut scenario_strings = args.get_strings("scenarios");
+    let scenario_file = args.get_string("scenario-file");
+    if !scenario_file.is_empty(){
+        let contents = fs::read_to_string(&scenario_file).expect("Couldn't read scenario file.");
+        scenario_strings.extend(
+            contents.split('\n').map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        );
+    }
+    let mut scenarios = Vec::new();
+    for string in scenario_strings{
+        match parse_scenario(&string){
+            Ok(scenario) => scenarios.push(scenario),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            },
+        }
+    }
+    if scenarios.is_empty(){
+        scenarios = default_scenarios();
+    }
+    let without_pending = if ts.iter().any(|t| t.status() == Status::Pending){
+        let mut state = State::new(&namebank);
+        state.skip_pending = true;
+        crate::core::hist(&mut state, &ts);
+        scale_values(&mut state.accounts, real_factor, &namebank);
+        Some(state)
+    } else {
+        None
+    };
     let norm_fac = summary(
         &SummaryData{
             namebank: &namebank,
             state: &state,
+            without_pending: without_pending.as_ref(),
             hist: &hist,
             redact,
             redact_map: &redact_map,
             includes: &args.get_strings("summary-accounts"),
             rounding: &value_rounding,
             min_asset_worth,
+            date,
+            scenarios: &scenarios,
+            yield_rate: args.get_float("yield-rate"),
+            years_after: args.get_integer("years-after") as f32,
+            real_base,
+            accrued: if args.get_bool("accrued") { Some(&accrued) } else { None },
         }
     );
 
+    let months = args.get_integer("forecast").max(0) as usize;
+    // the months to forecast follow the dated history
+    let forecast_from = if months > 0 && (hist.is_empty() || start_date.1 == 0){
+        println!("There are no dated transactions to forecast from.");
+        None
+    } else if months > 0{
+        let mut last = start_date;
+        for _ in 1..hist.len(){
+            last = next_month(last);
+        }
+        let roi = args.get_float("roi-mean");
+        let (frames, recurring) = forecast(&namebank, &state, &ts, &hist, last, months, roi);
+        print_forecast(&namebank, &hist, &frames, &recurring, &args.get_strings("graph-accounts"), redact);
+        let from = hist.len();
+        hist.extend(frames);
+        Some(from)
+    } else {
+        None
+    };
+
     if draw_graph{
         let colours = get_graph_colours(&args);
         let includes = args.get_strings("graph-accounts");
         if !includes.is_empty(){
             let includes = includes.iter().map(|s| s.as_str()).collect::<Vec<_>>();
             graph(
-                norm_fac, &namebank, &ts, &includes, &redact_map, colours, &browser,
-                year_digits, use_month_name
+                norm_fac, &namebank, &hist, start_date, &includes, &redact_map, colours, &browser,
+                year_digits, use_month_name, forecast_from
             );
         }
     }
 }
 
+/// The namebank, transactions, the line number of each transaction and the last date
+fn parse(contents: &str, check_order: bool) -> Option<(NameBank, Vec<Trans>, Vec<usize>, Date)>{
+    let mut namebank = NameBank::new();
+    read_aliases(contents, &mut namebank);
+    let mut date = Date::default();
+    let mut prev_date_sum = 0;
+    let mut ts = Vec::new();
+    let mut lines = Vec::new();
+    let mut errs = Vec::new();
+    for (ln, line) in contents.split('\n').enumerate() {
+        // the id is shown with the error, also when the line does not parse
+        let id = line.split(',').skip(1).find_map(|field| field.strip_prefix('@'))
+            .filter(|id| !id.is_empty()).map(|id| format!(" @{}", id)).unwrap_or_default();
+        let parse_res = line.to_string().into_trans(&mut namebank, &mut date);
+        match parse_res {
+            Some(Err(e)) => errs.push((ln + 1, id, e)), // lines start at 1, indices at 0
+            Some(Ok(t)) => {
+                let date_sum = date_sum(date);
+                if check_order && date_sum < prev_date_sum {
+                    errs.push((ln + 1, id, TransErr::OrderError(date)));
+                }
+                prev_date_sum = date_sum;
+                ts.push(t);
+                lines.push(ln + 1);
+            },
+            _ => {  },
+        }
+    }
+    if !errs.is_empty(){
+        println!("The following errors have been found while parsing:");
+        for (line, id, err) in errs{
+            println!("  {}{}:\t{}", line, id, err);
+        }
+        return None;
+    }
+    Some((namebank, ts, lines, date))
+}
+
+/// An optional date given with a flag
+fn date_flag(args: &lapp::Args, flag: &str) -> Result<Option<Date>, String>{
+    let string = args.get_string(flag);
+    if string.is_empty(){
+        return Ok(None);
+    }
+    parse_date(&string).map(Some).ok_or_else(|| format!("Could not parse date '{}'.", string))
+}
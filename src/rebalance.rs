@@ -0,0 +1,174 @@
+use crate::core::*;
+
+use zen_colour::*;
+
+/// Assets with a target together, an asset with its own target is not part of its class
+struct Group{
+    name: String,
+    target: f32, // share of everything with a target
+    tolerance: f32,
+    assets: Vec<(usize, f32)>, // asset, worth
+}
+
+impl Group{
+    fn worth(&self) -> f32{
+        self.assets.iter().map(|(_, worth)| worth).sum()
+    }
+
+    /// Split an amount over the assets of the group by their worth,
+    /// all of it to the first asset when nothing is owned yet
+    fn split(&self, amount: f32) -> Vec<(usize, f32)>{
+        let worth = self.worth();
+        if worth <= 0.0{
+            return self.assets.iter().take(1).map(|(asset, _)| (*asset, amount)).collect();
+        }
+        self.assets.iter().map(|(asset, w)| (*asset, amount * w / worth)).collect()
+    }
+}
+
+/// Compare the holdings with the tgt lines and give the trades that bring them back to their targets,
+/// with new cash only buy with that instead
+pub fn rebalance(nb: &NameBank, state: &State, date: Date, cash: f32, redact: bool){
+    let (textc, infoc, namec, posc, negc, fracc, b, r) =
+        (DEFAULT, MAGENTA, BLUE, GREEN, RED, YELLOW, BOLD, RESET);
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+    // asset amounts can be small, like some bitcoin
+    let amount = |v: f32| (v * 1000000.0).round() / 1000000.0;
+
+    if state.targets.is_empty(){
+        println!("There are no targets, add tgt lines to compare your holdings with.");
+        return;
+    }
+    let mut groups = state.targets.iter().map(|(name, share, tolerance)| Group{
+        name: name.clone(), target: *share, tolerance: *tolerance, assets: Vec::new(),
+    }).collect::<Vec<_>>();
+    let mut untargeted = 0.0;
+    let mut unpriced = Vec::new();
+    for asset in 0..state.asset_amounts.len(){
+        let name = nb.asset_name(asset);
+        let own = groups.iter().position(|group| group.name == name);
+        let class = state.asset_classes.get(&asset)
+            .and_then(|class| groups.iter().position(|group| &group.name == class));
+        let price = state.asset_prices[asset];
+        let worth = state.asset_amounts[asset].max(0.0) * price;
+        match own.or(class){
+            Some(_) if price == 0.0 => unpriced.push(name),
+            Some(group) => groups[group].assets.push((asset, worth)),
+            None => untargeted += worth,
+        }
+    }
+    let target_sum: f32 = groups.iter().map(|group| group.target).sum();
+    let total: f32 = groups.iter().map(|group| group.worth()).sum();
+    if target_sum <= 0.0 || groups.iter().all(|group| group.assets.is_empty()){
+        println!("No priced asset has a target.");
+        return;
+    }
+
+    println!("{}{b}Allocation{r} (target, current, drift):", infoc);
+    let mut out_of_band = false;
+    for group in &groups{
+        let target = group.target / target_sum * 100.0;
+        let share = if total > 0.0 { group.worth() / total * 100.0 } else { 0.0 };
+        let drift = share - target;
+        let (driftc, band) = if drift.abs() > group.tolerance{
+            out_of_band = true;
+            (negc, format!(" {}out of band{}", negc, textc))
+        } else {
+            (posc, String::new())
+        };
+        let worth = if redact{
+            String::new()
+        } else {
+            format!(" worth {}{}{}", posc, val(group.worth()), textc)
+        };
+        println!("  {}{}{}: {}{}%{} ±{}, {}{}%{}{}, {}{:+}{}{}",
+            namec, group.name, textc, fracc, val(target), textc, val(group.tolerance),
+            fracc, val(share), textc, worth, driftc, val(drift), textc, band
+        );
+    }
+    if (target_sum - 100.0).abs() >= 0.005{
+        println!("  {}The targets add up to {}{}%{}, they are scaled to 100%.",
+            textc, fracc, val(target_sum), textc
+        );
+    }
+    if untargeted >= 0.005 && !redact{
+        println!("  {}Assets worth {}{}{} have no target and are left out.",
+            textc, posc, val(untargeted), textc
+        );
+    }
+    if !unpriced.is_empty(){
+        println!("  {}No price for {}, left out.", textc, unpriced.join(", "));
+    }
+    if redact{
+        return;
+    }
+
+    // what every group should be worth after the trades
+    let new_total = total + cash.max(0.0);
+    let mut deltas = groups.iter()
+        .map(|group| group.target / target_sum * new_total - group.worth())
+        .collect::<Vec<_>>();
+    if cash > 0.0{
+        // only buying, the cash goes to what is furthest below target
+        let short: f32 = deltas.iter().map(|d| d.max(0.0)).sum();
+        for (delta, group) in deltas.iter_mut().zip(&groups){
+            *delta = if short > cash{
+                delta.max(0.0) / short * cash
+            } else {
+                delta.max(0.0) + (cash - short) * group.target / target_sum
+            };
+        }
+        println!("{}{b}Buy with {}{}{r}:", infoc, posc, val(cash));
+        for (group, delta) in groups.iter().zip(deltas){
+            for (asset, worth) in group.split(delta){
+                if worth < 0.005 { continue; }
+                let price = state.asset_prices[asset];
+                println!("  {}{}{} worth {}{}{} ({}{}{} at {})",
+                    namec, nb.asset_name(asset), textc, posc, val(worth), textc,
+                    posc, amount(worth / price), textc, val(price)
+                );
+            }
+        }
+        return;
+    }
+    if !out_of_band{
+        println!("{}Everything is within its band, no trades needed.", textc);
+        return;
+    }
+
+    // sell what is above target for what is below it, one con line per pair,
+    // fiat is not converted with con but bought and sold with
+    let mut sells = Vec::new();
+    let mut buys = Vec::new();
+    for (group, delta) in groups.iter().zip(deltas){
+        let trades = group.split(delta.abs());
+        if delta < 0.0 { sells.extend(trades); } else { buys.extend(trades); }
+    }
+    println!("{}{b}Trades to rebalance{r}:", infoc);
+    let (mut si, mut bi) = (0, 0);
+    while si < sells.len() && bi < buys.len(){
+        let worth = sells[si].1.min(buys[bi].1);
+        if worth >= 0.005{
+            let (src, dst) = (sells[si].0, buys[bi].0);
+            let (src_amount, dst_amount) =
+                (amount(worth / state.asset_prices[src]), amount(worth / state.asset_prices[dst]));
+            if src == REAL_FIAT{
+                println!("  {}buy {}{}{} {} for {}{}{}",
+                    textc, posc, dst_amount, textc, nb.asset_name(dst), negc, val(worth), textc
+                );
+            } else if dst == REAL_FIAT{
+                println!("  {}sell {}{}{} {} for {}{}{}",
+                    textc, negc, src_amount, textc, nb.asset_name(src), posc, val(worth), textc
+                );
+            } else {
+                println!("  {}con,{},{},{},{},{}",
+                    textc, format_date(date), nb.asset_name(src), src_amount, nb.asset_name(dst), dst_amount
+                );
+            }
+        }
+        sells[si].1 -= worth;
+        buys[bi].1 -= worth;
+        if sells[si].1 < 0.005 { si += 1; }
+        if buys[bi].1 < 0.005 { bi += 1; }
+    }
+}
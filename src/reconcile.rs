@@ -0,0 +1,114 @@
+use crate::core::*;
+
+use zen_colour::*;
+
+/// Only the most recent uncleared transactions are combined, the search doubles with each one
+const MAX_CANDIDATES: usize = 20;
+const MAX_COMBINATIONS: usize = 10;
+
+/// Compare the cleared balance of an account with a statement and look for the uncleared
+/// transactions that explain the difference
+pub fn reconcile(
+    nb: &NameBank, ts: &[Trans], origins: &[Origin], contents: &str,
+    account: &str, balance: f32, at: Option<Date>)
+{
+    let (textc, infoc, namec, posc, negc, b, r) =
+        (DEFAULT, MAGENTA, BLUE, GREEN, RED, BOLD, RESET);
+    let pncol = |v: f32| if v < 0.0 { negc } else { posc };
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+
+    let id = match nb.find_account(account){
+        Some(id) => id,
+        None => {
+            println!("Unknown account '{}', give it with --account.", account);
+            return;
+        },
+    };
+    let at_sum = at.map(date_sum);
+
+    // the change every transaction makes to the (rolled up) balance of the account
+    let mut state = State::new(nb);
+    let mut cleared = 0.0;
+    let mut total = 0.0;
+    let mut uncleared = Vec::new();
+    for (index, trans) in ts.iter().enumerate(){
+        if let Some(at_sum) = at_sum{
+            if date_sum(trans.date()) > at_sum{ break; }
+        }
+        let before = nb.rollup(&state.accounts)[id];
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        let delta = nb.rollup(&state.accounts)[id] - before;
+        if delta.abs() < 0.005{ continue; }
+        total += delta;
+        if trans.status() == Status::Cleared{
+            cleared += delta;
+        } else {
+            uncleared.push((index, delta));
+        }
+    }
+    let difference = balance - cleared;
+
+    let date = at.map(format_date).unwrap_or_else(|| String::from("the last date"));
+    println!("{}{b}Reconcile{r}:", infoc);
+    println!("  {}Statement balance of {}{}{} on {}: {}{}{}",
+        textc, namec, account, textc, date, pncol(balance), val(balance), textc);
+    println!("  {}Cleared balance: {}{}{}", textc, pncol(cleared), val(cleared), textc);
+    println!("  {}Balance with uncleared transactions: {}{}{}",
+        textc, pncol(total), val(total), textc);
+    println!("  {}Difference: {}{}{}", textc, pncol(difference), val(difference), textc);
+
+    let raw_lines = contents.split('\n').collect::<Vec<_>>();
+    let print_trans = |index: usize, delta: f32|{
+        let status = if ts[index].status() == Status::Pending { "pending" } else { "unmarked" };
+        let id = ts[index].id().map(|id| format!(" @{}", id)).unwrap_or_default();
+        // posted interest has no line of its own
+        let text = match origins[index]{
+            Origin::Line(line) => raw_lines[line - 1].trim().to_string(),
+            Origin::Interest(_) => format!("{} {}", format_date(ts[index].date()), INTEREST_TAG),
+        };
+        println!("    {}{}{}: {}{}{} ({}) {}",
+            textc, origins[index], id, pncol(delta), val(delta), textc, status, text
+        );
+    };
+
+    println!("{}{b}Uncleared{r}:", infoc);
+    if uncleared.is_empty(){
+        println!("  {}There are no uncleared transactions.", textc);
+    }
+    for (index, delta) in &uncleared{
+        print_trans(*index, *delta);
+    }
+
+    println!("{}{b}Explanations{r}:", infoc);
+    if difference.abs() < 0.005{
+        println!("  {}The cleared balance matches the statement.", textc);
+        return;
+    }
+    let candidates = &uncleared[uncleared.len().saturating_sub(MAX_CANDIDATES)..];
+    let mut combinations = Vec::new();
+    for mask in 1..(1u32 << candidates.len()){
+        let sum: f32 = candidates.iter().enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, (_, delta))| delta).sum();
+        if (sum - difference).abs() < 0.005{
+            combinations.push(mask);
+        }
+    }
+    if combinations.is_empty(){
+        println!("  {}No combination of uncleared transactions explains the difference.", textc);
+        return;
+    }
+    // the smallest combinations are the most likely ones
+    combinations.sort_by_key(|mask| mask.count_ones());
+    for (nr, mask) in combinations.iter().take(MAX_COMBINATIONS).enumerate(){
+        println!("  {}Option {}:", textc, nr + 1);
+        for (i, (index, delta)) in candidates.iter().enumerate(){
+            if mask & (1 << i) != 0{
+                print_trans(*index, *delta);
+            }
+        }
+    }
+    if combinations.len() > MAX_COMBINATIONS{
+        println!("  {}And {} more.", textc, combinations.len() - MAX_COMBINATIONS);
+    }
+}
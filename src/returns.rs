@@ -0,0 +1,124 @@
+use crate::core::*;
+
+use zen_colour::*;
+
+/// Returns of an investment over one period
+struct Period{
+    label: String,
+    start_index: f32,
+    cash_flows: Vec<(i64, f64)>, // day, money received (negative when invested)
+}
+
+impl Period{
+    fn new(label: String, day: i64, index: f32, value: f32) -> Self{
+        let mut cash_flows = Vec::new();
+        if value.abs() >= 0.005{
+            cash_flows.push((day, -value as f64));
+        }
+        Self{ label, start_index: index, cash_flows }
+    }
+
+    /// Time-weighted and money-weighted return, none when nothing was invested
+    fn close(mut self, day: i64, index: f32, value: f32) -> Option<(String, f32, Option<f64>)>{
+        if self.cash_flows.is_empty(){
+            return None;
+        }
+        self.cash_flows.push((day, value as f64));
+        let twr = index / self.start_index - 1.0;
+        Some((self.label, twr, xirr(&self.cash_flows)))
+    }
+}
+
+/// Time-weighted and money-weighted returns of every investment account, per year and in total
+pub fn returns(nb: &NameBank, ts: &[Trans]){
+    let (textc, infoc, namec, posc, negc, b, r) =
+        (DEFAULT, MAGENTA, BLUE, GREEN, RED, BOLD, RESET);
+
+    let mut state = State::new(nb);
+    let mut series = vec![ROI];
+    series.extend(nb.roi_accounts().iter().map(|(_, roi, _)| *roi));
+    let mut names = vec![String::from("All investments")];
+    names.extend(nb.roi_accounts().iter().map(|(account, ..)| nb.account_name(*account)));
+
+    let mut year = 0;
+    let mut day = 0;
+    let mut years: Vec<Period> = Vec::new();
+    let mut totals: Vec<Period> = Vec::new();
+    let mut rows = vec![Vec::new(); series.len()];
+    for trans in ts{
+        let date = trans.date();
+        if date.2 != 0{
+            if year == 0{
+                year = date.2;
+                day = date_days(date);
+                let investments = state.investments();
+                for (i, (value, _)) in investments.iter().enumerate(){
+                    let index = state.accounts[series[i]];
+                    years.push(Period::new(year.to_string(), day, index, *value));
+                    totals.push(Period::new(String::from("total"), day, index, *value));
+                }
+            }
+            // close every year up to the one of this transaction
+            while date.2 > year{
+                let end = date_days((31, 12, year));
+                let investments = state.investments();
+                for (i, period) in years.iter_mut().enumerate(){
+                    let index = state.accounts[series[i]];
+                    let value = investments[i].0;
+                    let next = Period::new((year + 1).to_string(), end + 1, index, value);
+                    rows[i].extend(std::mem::replace(period, next).close(end, index, value));
+                }
+                year += 1;
+            }
+            day = date_days(date);
+        }
+        let counted = state.cash_flows().iter().map(Vec::len).collect::<Vec<_>>();
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        // only dated transactions have cash flows, the periods start at the first one
+        for (i, cash_flows) in state.cash_flows().iter().enumerate(){
+            for cash_flow in &cash_flows[counted[i]..]{
+                years[i].cash_flows.push(*cash_flow);
+                totals[i].cash_flows.push(*cash_flow);
+            }
+        }
+    }
+    let investments = state.investments();
+    for (i, (period, total)) in years.into_iter().zip(totals).enumerate(){
+        let index = state.accounts[series[i]];
+        let value = investments[i].0;
+        rows[i].extend(period.close(day, index, value));
+        rows[i].extend(total.close(day, index, value));
+    }
+
+    let pncol = |v: f32| if v < 0.0 { negc } else { posc };
+    let pct = |v: f32| (v * 10000.0).round() / 100.0;
+    println!("{}{b}Returns{r} (time-weighted, money-weighted a year):", infoc);
+    for (name, rows) in names.iter().zip(rows){
+        if rows.is_empty() { continue; }
+        println!("  {}{}{}:", namec, name, textc);
+        for (label, twr, mwr) in rows{
+            let mwr = match mwr{
+                Some(mwr) => format!("{}{}{}%", pncol(mwr as f32), pct(mwr as f32), textc),
+                None => String::from("-"),
+            };
+            println!("    {}{}: {}{}{}%, {}", textc, label, pncol(twr), pct(twr), textc, mwr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn period_returns(){
+        // the index grew 10% and the money put in at the start is worth 10% more after a year
+        let period = Period::new(String::from("2021"), 0, 1.0, 1000.0);
+        let (label, twr, mwr) = period.close(365, 1.1, 1100.0).unwrap();
+        assert_eq!(label, "2021");
+        assert!((twr - 0.1).abs() < 1e-6);
+        assert!((mwr.unwrap() - 0.1).abs() < 1e-6);
+        // a period in which nothing was invested has no returns
+        assert!(Period::new(String::from("2022"), 0, 1.0, 0.0).close(365, 1.0, 0.0).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::core::*;
+
+use std::collections::HashMap;
+use std::process::Command;
+use std::fs::File;
+use std::io::prelude::*;
+
+const WIDTH: f32 = 1200.0;
+const HEIGHT: f32 = 700.0;
+const MARGIN: f32 = 40.0;
+const NODE_WIDTH: f32 = 16.0;
+const NODE_GAP: f32 = 20.0;
+
+/// Money comes from income, goes through accounts and ends up as spending or costs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Node{
+    Income,
+    Account(usize),
+    Spending,
+    Costs,
+}
+
+/// Draw where money came from and went to between two dates as a Sankey diagram in sankey.html
+pub fn sankey(
+    nb: &NameBank, ts: &[Trans], from: Option<Date>, to: Option<Date>, redact: bool,
+    colours: &[String], browser: &str)
+{
+    // every transaction has to be applied for the amounts, only the ones in the range count
+    let mut state = State::new(nb);
+    state.transfer_log = Some(Vec::new());
+    let mut amounts: HashMap<(Node, Node), f32> = HashMap::new();
+    for trans in ts{
+        update(std::slice::from_ref(trans), &mut state, None, None);
+        let log = state.transfer_log.replace(Vec::new()).unwrap_or_default();
+        let day = date_sum(trans.date());
+        if from.map(|from| day < date_sum(from)).unwrap_or(false){ continue; }
+        if to.map(|to| day > date_sum(to)).unwrap_or(false){ break; }
+        for (src, dst, sub, add) in log{
+            let src_node = if src == NULL { Node::Income } else { Node::Account(src) };
+            let dst_node = if dst == NULL { Node::Spending } else { Node::Account(dst) };
+            *amounts.entry((src_node, dst_node)).or_insert(0.0) += add.min(sub);
+            // transaction costs, or a gain when more arrives than left
+            if sub > add{
+                *amounts.entry((src_node, Node::Costs)).or_insert(0.0) += sub - add;
+            } else if add > sub{
+                *amounts.entry((Node::Income, dst_node)).or_insert(0.0) += add - sub;
+            }
+        }
+    }
+
+    // money moved back and forth between two accounts only counts once, in one direction
+    let mut edges = Vec::new();
+    for (&(src, dst), &amount) in &amounts{
+        let back = if src != dst { amounts.get(&(dst, src)).copied().unwrap_or(0.0) } else { amount };
+        let net = amount - back;
+        if net >= 0.005{
+            edges.push((src, dst, net));
+        }
+    }
+    edges.sort_by_key(|(src, dst, _)| (*src, *dst));
+    if edges.is_empty(){
+        println!("No money moved in this period.");
+        return;
+    }
+
+    // columns by the longest path from income, bounded so cycles end
+    let mut nodes = edges.iter().flat_map(|(src, dst, _)| [*src, *dst]).collect::<Vec<_>>();
+    nodes.sort();
+    nodes.dedup();
+    let mut column: HashMap<Node, usize> = nodes.iter().map(|node| (*node, match node{
+        Node::Income => 0,
+        _ => 1,
+    })).collect();
+    for _ in 0..nodes.len(){
+        for (src, dst, _) in &edges{
+            if let Node::Account(_) = dst{
+                let col = (column[src] + 1).min(nodes.len());
+                if col > column[dst]{
+                    column.insert(*dst, col);
+                }
+            }
+        }
+    }
+    let last = column.iter().filter(|(node, _)| matches!(node, Node::Account(_)))
+        .map(|(_, col)| *col).max().unwrap_or(0) + 1;
+    column.insert(Node::Spending, last);
+    column.insert(Node::Costs, last);
+    let columns = column.values().copied().max().unwrap_or(0) + 1;
+
+    // a node is as high as the most money going in or out of it
+    let mut ins: HashMap<Node, f32> = HashMap::new();
+    let mut outs: HashMap<Node, f32> = HashMap::new();
+    for (src, dst, amount) in &edges{
+        *outs.entry(*src).or_insert(0.0) += amount;
+        *ins.entry(*dst).or_insert(0.0) += amount;
+    }
+    let size = |node: &Node| ins.get(node).copied().unwrap_or(0.0).max(outs.get(node).copied().unwrap_or(0.0));
+    let mut scale = f32::MAX;
+    for col in 0..columns{
+        let in_col = nodes.iter().filter(|node| column[node] == col).collect::<Vec<_>>();
+        if in_col.is_empty() { continue; }
+        let total: f32 = in_col.iter().map(|node| size(node)).sum();
+        let room = HEIGHT - 2.0 * MARGIN - NODE_GAP * (in_col.len() - 1) as f32;
+        scale = scale.min(room / total);
+    }
+    let col_width = (WIDTH - 2.0 * MARGIN - NODE_WIDTH) / (columns - 1).max(1) as f32;
+    let mut pos: HashMap<Node, (f32, f32)> = HashMap::new(); // left, top
+    for col in 0..columns{
+        let mut y = MARGIN;
+        for node in nodes.iter().filter(|node| column[node] == col){
+            pos.insert(*node, (MARGIN + col as f32 * col_width, y));
+            y += size(node) * scale + NODE_GAP;
+        }
+    }
+
+    let name = |node: &Node| match node{
+        Node::Income => String::from("income"),
+        Node::Spending => String::from("spending"),
+        Node::Costs => String::from("costs"),
+        Node::Account(id) => escape(&nb.account_name(*id)),
+    };
+    let palette = colours.iter().skip(2).collect::<Vec<_>>();
+    let colour = |node: &Node| {
+        let i = nodes.iter().position(|n| n == node).unwrap_or(0);
+        palette.get(i % palette.len().max(1)).map(|c| c.as_str()).unwrap_or("#888888")
+    };
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+        WIDTH, HEIGHT
+    );
+    svg.push_str(&format!("<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n", colours[0]));
+    // flows leave and enter nodes stacked from the top, ordered by where they go to and come from
+    let mut out_offset: HashMap<Node, f32> = HashMap::new();
+    let mut in_offset: HashMap<Node, f32> = HashMap::new();
+    let mut ordered = edges.clone();
+    ordered.sort_by(|a, b| pos[&a.1].1.partial_cmp(&pos[&b.1].1).unwrap_or(std::cmp::Ordering::Equal));
+    for (src, dst, amount) in &ordered{
+        let width = amount * scale;
+        let (sx, sy) = pos[src];
+        let (dx, dy) = pos[dst];
+        let so = out_offset.entry(*src).or_insert(0.0);
+        let y0 = sy + *so + width / 2.0;
+        *so += width;
+        let io = in_offset.entry(*dst).or_insert(0.0);
+        let y1 = dy + *io + width / 2.0;
+        *io += width;
+        let (x0, x1) = (sx + NODE_WIDTH, dx);
+        let xm = (x0 + x1) / 2.0;
+        let title = if redact { String::new() } else { format!(": {}", val(*amount)) };
+        svg.push_str(&format!(
+            "<path d=\"M {x0} {y0} C {xm} {y0} {xm} {y1} {x1} {y1}\" fill=\"none\" stroke=\"{c}\" \
+            stroke-opacity=\"0.5\" stroke-width=\"{w}\"><title>{s} to {d}{t}</title></path>\n",
+            x0 = x0, y0 = y0, xm = xm, x1 = x1, y1 = y1, c = colour(src), w = width.max(1.0),
+            s = name(src), d = name(dst), t = title
+        ));
+    }
+    for node in &nodes{
+        let (x, y) = pos[node];
+        let height = (size(node) * scale).max(1.0);
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            x, y, NODE_WIDTH, height, colour(node)
+        ));
+        let label = if redact { name(node) } else { format!("{} {}", name(node), val(size(node))) };
+        // labels go on the inside of the diagram
+        let (lx, anchor) = if column[node] + 1 == columns{
+            (x - 4.0, "end")
+        } else {
+            (x + NODE_WIDTH + 4.0, "start")
+        };
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\" text-anchor=\"{}\" dominant-baseline=\"middle\">{}</text>\n",
+            lx, y + height / 2.0, colours[1], anchor, label
+        ));
+    }
+    svg.push_str("</svg>\n");
+
+    let range = match (from, to){
+        (Some(from), Some(to)) => format!(" from {} to {}", format_date(from), format_date(to)),
+        (Some(from), None) => format!(" from {}", format_date(from)),
+        (None, Some(to)) => format!(" up to {}", format_date(to)),
+        (None, None) => String::new(),
+    };
+    let page = format!(
+        "<html>\n<head><title>Cash flow{r}</title></head>\n<body style=\"background: {bg}; color: {fg}; font-family: sans-serif;\">\n<h3>Cash flow{r}</h3>\n{svg}</body>\n</html>\n",
+        r = range, bg = colours[0], fg = colours[1], svg = svg
+    );
+    let mut file = File::create("sankey.html").expect("Could not create file!");
+    file.write_all(page.as_bytes()).expect("Could not write to file!");
+    Command::new(browser).arg("sankey.html").output().unwrap_or_else(|_| panic!("Could not open sankey in {}!", browser));
+}
+
+/// Write text so it shows as is in the html
+fn escape(text: &str) -> String{
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+        .replace('"', "&quot;").replace('\'', "&#39;")
+}
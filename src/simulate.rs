@@ -0,0 +1,179 @@
+use crate::core::*;
+use crate::summary::*;
+use crate::graph::*;
+
+use std::fs;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use zen_colour::*;
+
+/// Small xorshift generator, good enough to draw market years with
+struct Rng(u64);
+
+impl Rng{
+    fn new(seed: u64) -> Self{
+        // the state can never be zero
+        Self(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64{
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in (0, 1]
+    fn uniform(&mut self) -> f64{
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Normally distributed with the Box-Muller transform
+    fn normal(&mut self, mean: f64, sd: f64) -> f64{
+        let (u, v) = (self.uniform(), self.uniform());
+        mean + sd * (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    }
+}
+
+/// Where the yearly returns and inflation come from, in percentages
+enum Market{
+    Normal{ roi: (f64, f64), inflation: (f64, f64) }, // mean, standard deviation
+    History(Vec<(f64, f64)>), // roi, inflation of a past year
+}
+
+impl Market{
+    fn draw(&self, rng: &mut Rng) -> (f64, f64){
+        match self{
+            Market::Normal { roi, inflation } => (
+                rng.normal(roi.0, roi.1), rng.normal(inflation.0, inflation.1)
+            ),
+            // whole years are drawn so returns and inflation stay together
+            Market::History(years) => years[(rng.next() % years.len() as u64) as usize],
+        }
+    }
+}
+
+/// Read a history file with lines of year,roi,inflation in percentages
+fn read_history(file: &str) -> Result<Vec<(f64, f64)>, String>{
+    let contents = fs::read_to_string(file).map_err(|err| format!("Could not read {}: {}", file, err))?;
+    let mut years = Vec::new();
+    for (ln, line) in contents.split('\n').enumerate(){
+        let fields = line.split(',').map(|field| field.trim()).collect::<Vec<_>>();
+        if fields[0].is_empty() || fields[0].starts_with('#'){ continue; }
+        let parse = |i: usize| fields.get(i).and_then(|field| field.parse::<f64>().ok());
+        match (parse(1), parse(2)){
+            (Some(roi), Some(inflation)) => years.push((roi, inflation)),
+            _ => return Err(format!("Could not parse line {} of {}: '{}'", ln + 1, file, line)),
+        }
+    }
+    if years.is_empty(){
+        return Err(format!("{} has no years in it", file));
+    }
+    Ok(years)
+}
+
+/// Run many possible futures from what is owned now and spent the past year,
+/// report how likely the money lasts and when it runs out
+pub fn simulate(args: &lapp::Args, nb: &NameBank, state: &State, hist: &[Vec<f32>], draw_graph: bool){
+    let (textc, infoc, posc, negc, fracc, b, r) =
+        (DEFAULT, MAGENTA, GREEN, RED, YELLOW, BOLD, RESET);
+    let paths = args.get_integer("paths").max(1) as usize;
+    let years = args.get_integer("years").clamp(1, 200) as usize;
+    let history = args.get_string("history");
+    let market = if history.is_empty(){
+        Market::Normal{
+            roi: (args.get_float("roi-mean") as f64, args.get_float("roi-sd") as f64),
+            inflation: (
+                args.get_float("inflation-mean") as f64, args.get_float("inflation-sd") as f64
+            ),
+        }
+    } else {
+        match read_history(&history){
+            Ok(years) => Market::History(years),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            },
+        }
+    };
+    let seed = match args.get_integer("seed"){
+        0 => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1),
+        seed => seed as u64,
+    };
+    let mut rng = Rng::new(seed);
+
+    let (min_sum, fiat, spend_past_12m) = runway_start(nb, state, hist);
+    if spend_past_12m <= 0.0{
+        println!("Nothing was spent the past year, there is nothing to simulate.");
+        return;
+    }
+    // same model as the metrics in the summary: spend monthly, only the non fiat part grows
+    let mut depleted = Vec::with_capacity(paths); // months until the money runs out
+    let mut worth = vec![Vec::with_capacity(paths); years + 1]; // worth at the start of every year
+    for _ in 0..paths{
+        let mut month_cost = spend_past_12m as f64 / 12.0;
+        let mut total = min_sum as f64;
+        let mut assets = (min_sum - fiat) as f64;
+        let mut months = None;
+        // a depleted path stays at zero
+        let mut path = vec![0.0; years + 1];
+        path[0] = total;
+        'path: for year in 0..years{
+            let (roi, inflation) = market.draw(&mut rng);
+            let roi = (1.0 + roi * 0.01).max(0.0).powf(1.0 / 12.0);
+            let inflation = (1.0 + inflation * 0.01).max(0.0).powf(1.0 / 12.0);
+            for month in 0..12{
+                if total <= month_cost{
+                    months = Some((year * 12 + month) as f64 + total / month_cost);
+                    break 'path;
+                }
+                total -= month_cost;
+                month_cost *= inflation;
+                assets = assets.min(total);
+                total -= assets;
+                assets *= roi;
+                total += assets;
+            }
+            path[year + 1] = total;
+        }
+        for (frame, total) in worth.iter_mut().zip(path){
+            frame.push(total);
+        }
+        depleted.push(months.unwrap_or(f64::INFINITY));
+    }
+    depleted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let success = depleted.iter().filter(|m| m.is_infinite()).count() as f64 / paths as f64;
+    let percentile = |sorted: &[f64], p: usize| sorted[(sorted.len() - 1) * p / 100];
+
+    let val = |v: f64| (v * 100.0).round() / 100.0;
+    println!("{}{b}Simulation{r}:", infoc);
+    println!("  {}Starting with {}{}{} and spending {}{}{} a year, {} paths of {} years",
+        textc, posc, val(min_sum as f64), textc, negc, val(spend_past_12m as f64), textc, paths, years
+    );
+    let succ = if success >= 0.5 { posc } else { negc };
+    println!("  {}The money lasts {}{}{} years in {}{}{}% of the paths",
+        textc, posc, years, textc, succ, val(success * 100.0), textc
+    );
+    for p in [5, 10, 25, 50]{
+        let months = percentile(&depleted, p);
+        if months.is_infinite(){
+            println!("  {}Less than {}{}{}% of the paths run out of money", textc, fracc, p, textc);
+        } else {
+            println!("  {}{}{}{}% of the paths run out of money within {}{}{} years",
+                textc, fracc, p, textc, negc, val(months / 12.0), textc
+            );
+        }
+    }
+
+    if draw_graph{
+        let percentiles = [5, 25, 50, 75, 95];
+        let header = percentiles.iter().map(|p| format!("{}%", p)).collect::<Vec<_>>();
+        let rows = worth.into_iter().enumerate().map(|(year, mut frame)| {
+            frame.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let values = percentiles.iter().map(|p| Some(percentile(&frame, *p) as f32)).collect();
+            (year.to_string(), values)
+        }).collect::<Vec<_>>();
+        let colours = get_graph_colours(args);
+        line_chart("Simulated worth", &header, &rows, None, &colours, &args.get_string("browser"));
+    }
+}
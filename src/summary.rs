@@ -7,29 +7,53 @@ use zen_colour::*;
 pub struct SummaryData<'a>{
     pub namebank: &'a NameBank,
     pub state: &'a State,
+    pub without_pending: Option<&'a State>, // only when there are pending transactions
     pub hist: &'a [Vec<f32>],
     pub redact: bool,
     pub redact_map: &'a HashMap<String, String>,
     pub includes: &'a [String],
     pub rounding: &'a str,
     pub min_asset_worth: f32,
+    pub date: Date, // of the last transaction
+    pub scenarios: &'a [Scenario],
+    pub yield_rate: f32,
+    pub years_after: f32, // months from which on durations are shown in years
+    pub real_base: Option<Date>, // values are in the money of this date
+    pub accrued: Option<&'a [f32]>, // interest not posted yet, per account
+}
+
+/// Sum of the positive balances of accounts that count towards net worth
+fn positive_sum(nb: &NameBank, state: &State) -> f32{
+    into_named_accounts(&state.accounts, nb, state).iter().skip(NR_BUILDIN_ACCOUNTS)
+        .map(|(_, x, stat)| if *x > 0.0 && !stat { *x } else { 0.0 })
+        .sum()
+}
+
+fn holdings_worth(state: &State) -> f32{
+    state.asset_amounts.iter().zip(state.asset_prices.iter()).map(|(a, p)| a * p).sum()
+}
+
+/// What runway metrics start from: the smallest estimate of what is owned, the fiat part of it
+/// and what was spent the past year
+pub fn runway_start(nb: &NameBank, state: &State, hist: &[Vec<f32>]) -> (f32, f32, f32){
+    let min_sum = positive_sum(nb, state).min(holdings_worth(state));
+    let spend_past_12m = hist.iter().rev().take(12).map(|frame| frame[SPENDING_MONTH]).sum();
+    (min_sum, state.asset_amounts[REAL_FIAT], spend_past_12m)
 }
 
 pub fn summary(d: &SummaryData) -> f32{
     let accounts = into_named_accounts(&d.state.accounts, d.namebank, d.state);
     let amounts = into_named_assets(&d.state.asset_amounts, d.namebank);
     let prices = into_named_assets(&d.state.asset_prices, d.namebank);
-    let it = amounts.iter().zip(prices.iter());
-    let pos_sum: f32 = accounts.iter().skip(NR_BUILDIN_ACCOUNTS).map(|(_, x, stat)|
-        if *x > 0.0 && !stat { *x } else { 0.0 }
-    ).sum();
-    let total_holdings_worth: f32 = it.fold(0.0, |acc, ((_, a), (_, p))| acc + a * p);
+    let pos_sum = positive_sum(d.namebank, d.state);
+    let total_holdings_worth = holdings_worth(d.state);
     let min_sum = pos_sum.min(total_holdings_worth);
     let norm_fac = if d.redact { min_sum } else { 1.0 };
     let net = accounts[NET].1;
     let debt = net - pos_sum;
     let r#yield = accounts[YIELD].1;
-    // let roi = accounts[ROI].1;
+    let roi = accounts[ROI].1;
+    let mwr = accounts[MWR].1;
     let assets = accounts[ASSETS].1;
     let sum_holding_error = pos_sum - total_holdings_worth;
     let fiat = amounts[0].1;
@@ -46,7 +70,7 @@ pub fn summary(d: &SummaryData) -> f32{
     let (textc, infoc, namec, posc, negc, fracc, b, r) =
         (DEFAULT, MAGENTA, BLUE, GREEN, RED, YELLOW, BOLD, RESET);
     let pncol = |v: f32| if v < 0.0 { negc } else { posc };
-    // let roicol = |v: f32| if v < 1.0 { negc } else { posc };
+    let roicol = |v: f32| if v < 1.0 { negc } else { posc };
 
     let val = match d.rounding {
         "none" => |v: f32| v,
@@ -54,10 +78,25 @@ pub fn summary(d: &SummaryData) -> f32{
         _ => |v: f32| (v * 100.0).round() / 100.0,
     };
 
+    // balances that change when pending transactions are left out show both values
+    let pending = |with: f32, without: Option<f32>| match without{
+        Some(without) if (with - without).abs() >= 0.005 => format!(
+            " {}({}{}{} without pending)", textc, pncol(without), val(without / norm_fac), textc
+        ),
+        _ => String::new(),
+    };
+
     println!("{}{b}General{r}:", infoc);
-    println!("  {}Net: {}{}{}", textc, pncol(net), val(net / norm_fac), textc);
+    if let Some(base) = d.real_base{
+        println!("  {}Values are in the money of {}{}{}", textc, fracc, format_date(base), textc);
+    }
+    println!("  {}Net: {}{}{}{}", textc, pncol(net), val(net / norm_fac), textc,
+        pending(net, d.without_pending.map(|s| s.accounts[NET]))
+    );
     println!("  {}Debt: {}{}{}", textc, pncol(debt), val(debt / norm_fac), textc);
     println!("  {}Yield: {}{}{}", textc, pncol(r#yield), val(r#yield / norm_fac), textc);
+    println!("  {}ROI: {}{}{}", textc, roicol(roi), val(roi), textc);
+    println!("  {}MWR: {}{}%{}", textc, pncol(mwr), val(mwr), textc);
     println!("  {}Assets: {}{}{}", textc, pncol(assets), val(assets / norm_fac), textc);
     println!("  {}Fiat: {}{}{}", textc, pncol(fiat), val(fiat / norm_fac), textc);
     println!("  {}Positive owned sum: {}{}", textc, posc, val(if d.redact { 1.0 } else { pos_sum }));
@@ -85,22 +124,43 @@ pub fn summary(d: &SummaryData) -> f32{
     println!("{b}{}Accounts{r}:", infoc);
     let mut to_print = Vec::new();
     let include_not_everything = !d.includes.is_empty();
-    for (name, amount, _) in &accounts{
+    // parents show the subtotal of themselves and their children
+    let rolled = d.namebank.rollup(&d.state.accounts);
+    let rolled_without = d.without_pending.map(|s| d.namebank.rollup(&s.accounts));
+    let rolled_accrued = d.accrued.map(|accrued| d.namebank.rollup(accrued));
+    for (id, depth) in d.namebank.account_tree(){
+        let name = &accounts[id].0;
         let index = d.includes.iter().position(|inc| inc == name);
         if include_not_everything && index.is_none(){ continue; }
-        let val = *amount / norm_fac;
+        if !include_not_everything && d.namebank.account_closed(id).is_some(){ continue; }
+        let mut note = pending(rolled[id], rolled_without.as_ref().map(|r| r[id]));
+        match rolled_accrued.as_ref().map(|r| r[id]){
+            Some(accrued) if accrued.abs() >= 0.005 => note.push_str(&format!(
+                " {}({}{:+}{} accrued)", textc, pncol(accrued), val(accrued / norm_fac), textc
+            )),
+            _ => { },
+        }
+        // ratios mean nothing when normalised
+        let val = if is_ratio(id, d.namebank) { rolled[id] } else { rolled[id] / norm_fac };
         let name = if let Some(redacted) = d.redact_map.get(name){
             redacted
-        } else {
+        } else if include_not_everything {
             name
+        } else {
+            name.rsplit(':').next().unwrap_or(name)
+        };
+        let name = if include_not_everything {
+            name.to_string()
+        } else {
+            format!("{}{}", "  ".repeat(depth), name)
         };
-        to_print.push((name, val, index.unwrap_or(0)));
+        to_print.push((name, val, note, index.unwrap_or(0)));
     }
     if include_not_everything{
-        to_print.sort_by(|(_, _, i), (_, _, j)| i.cmp(j));
+        to_print.sort_by_key(|(_, _, _, i)| *i);
     }
-    for (name, aval, _) in to_print{
-        println!("  {}{}: {}{}", namec, name, pncol(aval), val(aval));
+    for (name, aval, note, _) in to_print{
+        println!("  {}{}: {}{}{}", namec, name, pncol(aval), val(aval), note);
     }
 
     println!("{b}{}Distribution{r}:", infoc);
@@ -137,56 +197,138 @@ pub fn summary(d: &SummaryData) -> f32{
 
     println!("{b}{}Metrics{r}:", infoc);
     let time_flat = net / spend_past_12m * 12.0;
-    let moy = |x: f32| if x.abs() > 24.0 { x / 12.0 } else { x }; // months or years
-    let moy_label = |x: f32| if x.abs() > 24.0 { "years" } else { "months" };
+    let years_after = d.years_after;
+    let moy = |x: f32| if x.abs() > years_after { x / 12.0 } else { x }; // months or years
+    let moy_label = |x: f32| if x.abs() > years_after { "years" } else { "months" };
     println!("  {}Your net worth is {}{}{} {} (no Inflation and ROI)",
         textc, pncol(time_flat), val(moy(time_flat)), textc, moy_label(time_flat));
-    println!("  {}A {}2{}% yield would give you {}{}{}% of your spending.",
-        textc, posc, textc, posc, val((min_sum * 0.02) / spend_past_12m * 100.0), textc);
-
-    let print_time_exp = |inflation_rate: f32, roi_rate: f32|{
-        let inflation = (1.0 + (inflation_rate * 0.01)).powf(1.0 / 12.0);
-        let roi = (1.0 + (roi_rate * 0.01)).powf(1.0 / 12.0);
-        let infc = if inflation > 1.0 { negc } else { posc };
-        let roic = if roi > 1.0 { posc } else { negc };
-        let mut month_cost = spend_past_12m / 12.0;
-        let mut assets = min_sum - fiat;
-        let mut total = min_sum;
-        let mut months = 0.0;
-        loop{
-            if months >= 1200.0{
-                println!("  {}Your assets are worth {}100+{} years ({}% Infl., {}% ROI)",
-                    textc, posc, textc, inflation_rate, roi_rate
-                );
-                return;
-            }
-            if total > month_cost{
-                total -= month_cost;
-                month_cost *= inflation;
-                months += 1.0;
-                assets = assets.min(total);
-                total -= assets;
-                assets *= roi;
-                total += assets;
-            } else {
-                months += total / month_cost;
-                println!("  {}Your assets are worth {}{}{} {} ({}{}{}% Infl., {}{}{}% ROI)",
-                    textc, pncol(months), val(moy(months)), textc, moy_label(months), infc,
-                    inflation_rate, textc, roic, roi_rate, textc
-                );
-                return;
-            }
-        }
-    };
+    println!("  {}A {}{}{}% yield would give you {}{}{}% of your spending.",
+        textc, posc, d.yield_rate, textc, posc,
+        val((min_sum * d.yield_rate * 0.01) / spend_past_12m * 100.0), textc);
 
-    print_time_exp(10.0, -10.0);
-    print_time_exp(10.0, -5.0);
-    print_time_exp(10.0, 0.0);
-    print_time_exp(5.0, -5.0);
-    print_time_exp(5.0, 0.0);
-    print_time_exp(5.0, 5.0);
-    print_time_exp(5.0, 6.0);
-    print_time_exp(5.0, 7.0);
-    print_time_exp(5.0, 9.0);
+    let mut table = vec![[
+        "", "Infl.", "ROI", "Spending", "Income", "Lump sums", "Your assets are worth"
+    ].map(|h| (h.to_string(), textc))];
+    for scenario in d.scenarios{
+        let spending = scenario.spending.unwrap_or(spend_past_12m / 12.0);
+        let lumps = scenario.lumps.iter()
+            .map(|(date, amount)| format!("{}: {}", format_date(*date), val(amount / norm_fac)))
+            .collect::<Vec<_>>().join(" ");
+        let runway = match runway(scenario, d.date, min_sum, fiat, spending){
+            Some(months) => (format!("{} {}", val(moy(months)), moy_label(months)), pncol(months)),
+            None => (String::from("100+ years"), posc),
+        };
+        table.push([
+            (scenario.name.clone(), namec),
+            (format!("{}%", scenario.inflation), if scenario.inflation > 0.0 { negc } else { posc }),
+            (format!("{}%", scenario.roi), if scenario.roi > 0.0 { posc } else { negc }),
+            (format!("{}", val(spending / norm_fac)), negc),
+            (format!("{}", val(scenario.income / norm_fac)), posc),
+            (lumps, textc),
+            runway,
+        ]);
+    }
+    // names and lump sums only get a column when a scenario has them
+    let columns = (0..7).filter(|col| table.iter().skip(1).any(|row| !row[*col].0.is_empty()))
+        .collect::<Vec<_>>();
+    let widths = (0..7).map(|col| table.iter().map(|row| row[col].0.chars().count()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    for row in table{
+        let cells = columns.iter().enumerate().map(|(i, col)|{
+            let (cell, colour) = &row[*col];
+            let width = if i + 1 == columns.len() { 0 } else { widths[*col] };
+            format!("{}{:<w$}{}", colour, cell, textc, w = width)
+        }).collect::<Vec<_>>();
+        println!("  {}", cells.join("  "));
+    }
     norm_fac
 }
+
+/// Assumptions to see how long your assets would last with, amounts are monthly and in today's money
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario{
+    pub name: String,
+    pub inflation: f32, // yearly %
+    pub roi: f32, // yearly %
+    pub spending: Option<f32>, // instead of the spending of the past year
+    pub income: f32,
+    pub lumps: Vec<(Date, f32)>, // one time income, or spending when negative
+}
+
+/// The scenarios the summary shows when none are given
+pub fn default_scenarios() -> Vec<Scenario>{
+    [(10.0, -10.0), (10.0, -5.0), (10.0, 0.0), (5.0, -5.0), (5.0, 0.0),
+        (5.0, 5.0), (5.0, 6.0), (5.0, 7.0), (5.0, 9.0)]
+        .iter().map(|(inflation, roi)| Scenario{
+            name: String::new(),
+            inflation: *inflation,
+            roi: *roi,
+            spending: None,
+            income: 0.0,
+            lumps: Vec::new(),
+        }).collect()
+}
+
+/// Parse a scenario like name=retire;inflation=3;roi=5;spending=1500;income=300;lump=01/01/2030:-5000
+pub fn parse_scenario(string: &str) -> Result<Scenario, String>{
+    let mut scenario = Scenario{
+        name: String::new(),
+        inflation: 0.0,
+        roi: 0.0,
+        spending: None,
+        income: 0.0,
+        lumps: Vec::new(),
+    };
+    let number = |value: &str| value.parse::<f32>()
+        .map_err(|_| format!("Could not parse '{}' in scenario '{}'", value, string));
+    for part in string.split(';').map(|part| part.trim()).filter(|part| !part.is_empty()){
+        let (key, value) = part.split_once('=')
+            .ok_or_else(|| format!("Expected key=value instead of '{}' in scenario '{}'", part, string))?;
+        match key.trim(){
+            "name" => scenario.name = value.trim().to_string(),
+            "inflation" => scenario.inflation = number(value.trim())?,
+            "roi" => scenario.roi = number(value.trim())?,
+            "spending" => scenario.spending = Some(number(value.trim())?),
+            "income" => scenario.income = number(value.trim())?,
+            "lump" => {
+                let lump = value.split_once(':').and_then(|(date, amount)|
+                    Some((parse_date(date.trim())?, amount.trim().parse::<f32>().ok()?))
+                ).ok_or_else(|| format!("Expected date:amount instead of '{}' in scenario '{}'", value, string))?;
+                scenario.lumps.push(lump);
+            },
+            key => return Err(format!("Unknown key '{}' in scenario '{}'", key, string)),
+        }
+    }
+    Ok(scenario)
+}
+
+/// Months until the assets run out, none when they last for more than 100 years
+fn runway(scenario: &Scenario, date: Date, min_sum: f32, fiat: f32, spending: f32) -> Option<f32>{
+    let inflation = (1.0 + (scenario.inflation * 0.01)).powf(1.0 / 12.0);
+    let roi = (1.0 + (scenario.roi * 0.01)).powf(1.0 / 12.0);
+    let now = date.2 as i32 * 12 + date.1 as i32;
+    let mut month_cost = spending - scenario.income;
+    let mut assets = min_sum - fiat;
+    let mut total = min_sum;
+    let mut months = 0.0;
+    for month in 0..1200{
+        // lumps in the past are already in the balances
+        for (date, amount) in &scenario.lumps{
+            if date.2 as i32 * 12 + date.1 as i32 - now == month{
+                total += amount;
+            }
+        }
+        if total > month_cost{
+            total -= month_cost;
+            month_cost *= inflation;
+            months += 1.0;
+            assets = assets.min(total);
+            total -= assets;
+            assets *= roi;
+            total += assets;
+        } else {
+            return Some(months + (total / month_cost).max(0.0));
+        }
+    }
+    None
+}
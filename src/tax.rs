@@ -0,0 +1,151 @@
+use crate::core::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+
+use zen_colour::*;
+
+/// Numbers of one tax year, named after the year it starts in
+struct TaxYear{
+    year: u16,
+    net: f32, // at the start of the tax year, like the next four
+    fiat: f32,
+    assets: f32,
+    debts: f32,
+    gains: f32, // realised during the tax year
+    income: f32,
+}
+
+/// Per tax year: what was owned and owed at its start, realised gains and income during it.
+/// Balances come from the month frames, so a tax year starts at the start of a month.
+/// With real values the gains are expressed like the frames, with the factor of their month.
+pub fn tax(
+    args: &lapp::Args, nb: &NameBank, state: &State, ts: &[Trans], hist: &[Vec<f32>], start: MonthDate,
+    real_factors: Option<&[f32]>)
+{
+    let (textc, infoc, namec, posc, negc, b, r) = (DEFAULT, MAGENTA, BLUE, GREEN, RED, BOLD, RESET);
+    let pncol = |v: f32| if v < 0.0 { negc } else { posc };
+    let val = |v: f32| (v * 100.0).round() / 100.0;
+    let first_month = args.get_integer("tax-year-start");
+    if !(1..=12).contains(&first_month){
+        println!("The tax year has to start in a month from 1 to 12.");
+        return;
+    }
+    let first_month = first_month as u8;
+    if hist.is_empty() || start.1 == 0{
+        println!("There are no dated transactions to report on.");
+        return;
+    }
+    let tax_year = |(month, year): MonthDate| if month >= first_month { year } else { year - 1 };
+    let months = |(month, year): MonthDate|
+        (year as i64 - start.1 as i64) * 12 + month as i64 - start.0 as i64;
+
+    // balances at the end of a month, nothing before the history and the last ones after it
+    let frame = |date: MonthDate| {
+        let i = months(date);
+        if i < 0 { None } else { hist.get(i as usize).or(hist.last()) }
+    };
+    let totals = |frame: Option<&Vec<f32>>| {
+        let mut totals = (0.0, 0.0, 0.0, 0.0); // net, fiat, assets, debts
+        if let Some(frame) = frame{
+            totals.0 = frame[NET];
+            for (id, balance) in frame.iter().enumerate().skip(NR_BUILDIN_ACCOUNTS){
+                match state.account_labels[id]{
+                    AccountLabel::Fiat => totals.1 += balance,
+                    AccountLabel::Assets => totals.2 += balance,
+                    AccountLabel::Debt => totals.3 += balance,
+                    _ => { },
+                }
+            }
+        }
+        totals
+    };
+    let last = {
+        let mut date = start;
+        for _ in 1..hist.len(){
+            date = next_month(date);
+        }
+        date
+    };
+    let mut years = Vec::new();
+    for year in tax_year(start)..=tax_year(last){
+        let before = if first_month == 1 { (12, year - 1) } else { (first_month - 1, year) };
+        let (net, fiat, assets, debts) = totals(frame(before));
+        let mut income = 0.0;
+        let mut month = (first_month, year);
+        for _ in 0..12{
+            if let Some(frame) = frame(month).filter(|_| months(month) < hist.len() as i64){
+                income += frame[RECEIVING_MONTH];
+            }
+            month = next_month(month);
+        }
+        years.push(TaxYear{ year, net, fiat, assets, debts, gains: 0.0, income });
+    }
+
+    // money taken out of an investment account realises the gains of the part taken out,
+    // the cost of what is left is the money put in minus the cost of what was taken out
+    let mut replay = State::new(nb);
+    let mut cost = vec![0.0; replay.accounts.len()];
+    for trans in ts{
+        let before = replay.accounts.clone();
+        let flows = replay.flows.clone();
+        update(std::slice::from_ref(trans), &mut replay, None, None);
+        let date = trans.date();
+        let year = if date.2 == 0 { tax_year(start) } else { tax_year((date.1, date.2)) };
+        let mut gains = 0.0;
+        for id in 0..cost.len(){
+            if replay.account_labels[id] != AccountLabel::Assets { continue; }
+            let flow = replay.flows[id] - flows[id];
+            if flow > 0.0{
+                cost[id] += flow;
+            } else if flow < 0.0 && before[id] > 0.0{
+                let part = (-flow / before[id]).min(1.0);
+                gains += -flow - cost[id] * part;
+                cost[id] -= cost[id] * part;
+            }
+        }
+        if let Some(factors) = real_factors{
+            let month = if date.2 == 0 { 0 } else { months((date.1, date.2)).max(0) as usize };
+            gains *= factors.get(month).or(factors.last()).copied().unwrap_or(1.0);
+        }
+        if let Some(y) = years.iter_mut().find(|y| y.year == year){
+            y.gains += gains;
+        }
+    }
+
+    let label = |year: u16| if first_month == 1{
+        year.to_string()
+    } else {
+        format!("{}/{}", year, year + 1)
+    };
+    let reference = |year: u16| format_date((1, first_month, year));
+    let redact = args.get_bool("redact");
+    println!("{}{b}Tax years{r}:", infoc);
+    for y in &years{
+        println!("  {}{}{}:", namec, label(y.year), textc);
+        if redact{
+            println!("    {}Values are redacted.", textc);
+            continue;
+        }
+        println!("    {}On {}: net worth {}{}{}, fiat {}{}{}, assets {}{}{}, debts {}{}{}",
+            textc, reference(y.year), pncol(y.net), val(y.net), textc, pncol(y.fiat), val(y.fiat), textc,
+            pncol(y.assets), val(y.assets), textc, pncol(y.debts), val(y.debts), textc
+        );
+        println!("    {}Realised gains {}{}{}, received {}{}{}",
+            textc, pncol(y.gains), val(y.gains), textc, pncol(y.income), val(y.income), textc
+        );
+    }
+
+    let csv = args.get_string("csv");
+    if csv.is_empty() || redact { return; }
+    let mut out = String::from("tax_year,reference_date,net_worth,fiat,assets,debts,realised_gains,received\n");
+    for y in &years{
+        out.push_str(&format!("{},{},{},{},{},{},{},{}\n",
+            label(y.year), reference(y.year), val(y.net), val(y.fiat), val(y.assets), val(y.debts),
+            val(y.gains), val(y.income)
+        ));
+    }
+    let mut file = File::create(&csv).expect("Could not create file!");
+    file.write_all(out.as_bytes()).expect("Could not write to file!");
+    println!("{}Written to {}.", textc, csv);
+}